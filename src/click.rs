@@ -0,0 +1,281 @@
+use rustc_serialize::json;
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use event::Event;
+use Block;
+
+/// A click on one of the blocks, as reported by i3bar.
+#[derive(Clone, Debug, PartialEq, RustcDecodable, RustcEncodable)]
+pub struct ClickEvent {
+    pub name: Option<String>,
+    pub instance: Option<String>,
+    pub button: usize,
+    pub x: i32,
+    pub y: i32,
+    pub relative_x: Option<i32>,
+    pub relative_y: Option<i32>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub modifiers: Option<Vec<String>>,
+}
+
+impl ClickEvent {
+    /// Whether this event was caused by a click on `block`.
+    pub fn is_on(&self, block: &Block) -> bool {
+        self.name.is_some() && self.name == block.name && self.instance == block.instance
+    }
+}
+
+/// A shell command that is run when a block is clicked with the given button.
+/// An action without an `instance` applies to every instance of the block.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct ClickAction {
+    pub name: String,
+    pub instance: Option<String>,
+    pub button: usize,
+    pub command: String,
+}
+
+impl ClickAction {
+    pub fn matches(&self, event: &ClickEvent) -> bool {
+        event.button == self.button
+            && event.name.as_ref() == Some(&self.name)
+            && (self.instance.is_none() || self.instance == event.instance)
+    }
+
+    /// Runs the command through `sh`, with the event and the fields of the
+    /// clicked block in i3blocks compatible environment variables. The command
+    /// is not waited on, and its output is discarded as our stdout is i3bar's.
+    pub fn run(&self, event: &ClickEvent, block: Option<&Block>) -> io::Result<()> {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(&self.command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit())
+            .env("BLOCK_NAME", &self.name)
+            .env("BLOCK_BUTTON", event.button.to_string())
+            .env("BLOCK_X", event.x.to_string())
+            .env("BLOCK_Y", event.y.to_string());
+
+        if let Some(ref instance) = event.instance {
+            cmd.env("BLOCK_INSTANCE", instance);
+        }
+        if let Some(block) = block {
+            cmd.env("BLOCK_FULL_TEXT", &block.full_text);
+            if let Some(ref short_text) = block.short_text {
+                cmd.env("BLOCK_SHORT_TEXT", short_text);
+            }
+            if let Some(ref color) = block.color {
+                cmd.env("BLOCK_COLOR", color);
+            }
+        }
+
+        let mut child = try!(cmd.spawn());
+        // Reap the command once it exits, so that it does not linger as a zombie
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+/// A long-running helper process, in the spirit of i3blocks, that is sent
+/// every click event as one line of JSON on its stdin. It answers each event
+/// with one line on its stdout: an empty line leaves the clicked block alone,
+/// anything else replaces the block's `full_text`.
+///
+/// The events are written on a separate thread, so that a handler that stops
+/// reading does not hold up the status line. The replies are sent to `events`
+/// as they arrive, and are paired up with the events they answer by `reply`.
+pub struct ClickHandler {
+    child: Child,
+    lines: Sender<String>,
+    pending: VecDeque<ClickEvent>,
+}
+
+impl ClickHandler {
+    pub fn spawn(command: &str, events: Sender<Event>) -> io::Result<ClickHandler> {
+        let mut child = try!(Command::new("sh").arg("-c").arg(command)
+                             .stdin(Stdio::piped())
+                             .stdout(Stdio::piped())
+                             .stderr(Stdio::inherit())
+                             .spawn());
+        // Both handles are present, as they were requested to be piped
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (lines, rx) = mpsc::channel();
+        thread::spawn(move || write_lines(stdin, rx));
+
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => if events.send(Event::ClickReply(line)).is_err() { break },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Ok(ClickHandler {
+            child: child,
+            lines: lines,
+            pending: VecDeque::new(),
+        })
+    }
+
+    pub fn send(&mut self, event: &ClickEvent) -> io::Result<()> {
+        let line = try!(json::encode(event).map_err(|e| Error::new(ErrorKind::InvalidData, e)));
+        try!(self.lines.send(line).map_err(|_| {
+            Error::new(ErrorKind::BrokenPipe, "the click handler stopped reading")
+        }));
+        self.pending.push_back(event.clone());
+        Ok(())
+    }
+
+    /// Pairs a reply from the handler with the event it answers.
+    pub fn reply(&mut self, reply: String) -> Option<(ClickEvent, String)> {
+        self.pending.pop_front().map(|event| (event, reply))
+    }
+}
+
+/// Writes every line from `lines` to `stdin`, until the handler goes away.
+fn write_lines(mut stdin: ChildStdin, lines: Receiver<String>) {
+    for line in lines {
+        if writeln!(stdin, "{}", line).and_then(|_| stdin.flush()).is_err() {
+            break;
+        }
+    }
+}
+
+impl Drop for ClickHandler {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Decodes one line of the infinite array of click events, returning `None`
+/// for the opening `[` and for empty lines.
+pub fn parse_event(line: &str) -> Option<Result<ClickEvent, json::DecoderError>> {
+    let line = line.trim();
+    let line = if line.starts_with(",") { line[1..].trim() } else { line };
+
+    if line.is_empty() || line == "[" {
+        None
+    } else {
+        Some(json::decode(line))
+    }
+}
+
+/// Reads click events from `input` on a separate thread, and sends them to
+/// `events`.
+pub fn spawn_reader<R: Read + Send + 'static>(input: R, events: Sender<Event>) {
+    thread::spawn(move || {
+        for line in BufReader::new(input).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            match parse_event(&line) {
+                Some(Ok(event)) => if events.send(Event::Click(event)).is_err() { break },
+                Some(Err(e)) => eprintln!("r3status: invalid click event `{}`: {}", line, e),
+                None => (),
+            }
+        }
+    });
+}
+
+#[test]
+fn test_parse_event() {
+    assert_eq!(None, parse_event("["));
+    assert_eq!(None, parse_event(""));
+
+    let line = r#",{"name":"volume","instance":"default.Master.0","button":4,"modifiers":["Mod1"],"x":1900,"y":10,"relative_x":12,"relative_y":10,"width":50,"height":22}"#;
+    let event = parse_event(line).unwrap().unwrap();
+    assert_eq!(Some("volume".to_string()), event.name);
+    assert_eq!(Some("default.Master.0".to_string()), event.instance);
+    assert_eq!(4, event.button);
+    assert_eq!((1900, 10), (event.x, event.y));
+    assert_eq!((Some(12), Some(10)), (event.relative_x, event.relative_y));
+    assert_eq!((Some(50), Some(22)), (event.width, event.height));
+    assert_eq!(Some(vec!["Mod1".to_string()]), event.modifiers);
+
+    // Older versions of i3bar only send the name, instance, button and position
+    let event = parse_event(r#"{"name":"disk_info","button":1,"x":10,"y":5}"#).unwrap().unwrap();
+    assert_eq!(None, event.instance);
+    assert_eq!(None, event.width);
+
+    assert!(parse_event(r#"{"name":"disk_info"}"#).unwrap().is_err());
+}
+
+#[test]
+fn test_click_action_matches() {
+    let event = parse_event(r#"{"name":"volume","instance":"default","button":4,"x":0,"y":0}"#)
+        .unwrap().unwrap();
+    let mut action = ClickAction {
+        name: "volume".to_string(),
+        instance: None,
+        button: 4,
+        command: "pactl set-sink-volume @DEFAULT_SINK@ +5%".to_string(),
+    };
+    assert!(action.matches(&event));
+
+    action.instance = Some("default".to_string());
+    assert!(action.matches(&event));
+
+    action.instance = Some("headphones".to_string());
+    assert!(!action.matches(&event));
+
+    action.instance = None;
+    action.button = 1;
+    assert!(!action.matches(&event));
+}
+
+#[test]
+fn test_click_handler() {
+    let event = parse_event(r#"{"name":"disk","instance":"/","button":1,"x":0,"y":0}"#)
+        .unwrap().unwrap();
+    let (tx, rx) = ::std::sync::mpsc::channel();
+    let mut handler = ClickHandler::spawn("read line; echo; read line; echo clicked", tx).unwrap();
+    handler.send(&event).unwrap();
+    handler.send(&event).unwrap();
+
+    let replies: Vec<_> = rx.iter().take(2).map(|e| match e {
+        Event::ClickReply(reply) => handler.reply(reply),
+        other => panic!("expected a reply, got {:?}", other),
+    }).collect();
+    assert_eq!(Some((event.clone(), String::new())), replies[0]);
+    assert_eq!(Some((event, "clicked".to_string())), replies[1]);
+    assert_eq!(None, handler.reply("unasked".to_string()));
+}
+
+#[test]
+fn test_click_handler_does_not_block() {
+    let event = parse_event(r#"{"name":"disk","button":1,"x":0,"y":0}"#).unwrap().unwrap();
+    let (tx, _rx) = ::std::sync::mpsc::channel();
+    let mut handler = ClickHandler::spawn("sleep 10", tx).unwrap();
+    // Far more than fits in the pipe to the handler, which never reads it
+    for _ in 0..10000 {
+        handler.send(&event).unwrap();
+    }
+}
+
+#[test]
+fn test_spawn_reader() {
+    let input = "[\n{\"name\":\"a\",\"button\":1,\"x\":0,\"y\":0}\n,{\"name\":\"b\",\"button\":3,\"x\":0,\"y\":0}\n";
+    let input = ::std::io::Cursor::new(input.as_bytes().to_vec());
+    let (tx, rx) = ::std::sync::mpsc::channel();
+    spawn_reader(input, tx);
+    let events: Vec<ClickEvent> = rx.iter().map(|e| match e {
+        Event::Click(event) => event,
+        other => panic!("expected a click, got {:?}", other),
+    }).collect();
+    assert_eq!(2, events.len());
+    assert_eq!(Some("b".to_string()), events[1].name);
+    assert_eq!(3, events[1].button);
+}
+
@@ -0,0 +1,118 @@
+use libc::c_int;
+use rustc_serialize::json;
+
+use std::fs::File;
+use std::io::{self, Error, ErrorKind, Read};
+use std::path::Path;
+
+use click::ClickAction;
+use filter::Filter;
+use notify::NotificationConfig;
+use placement::Placement;
+use threshold::Threshold;
+use upstream::Upstream;
+use utf8::Utf8Policy;
+use {signal, Header, PROTOCOL_VERSION};
+
+/// The r3status config file, a JSON object in which every key is optional.
+#[derive(Debug, Default, RustcDecodable)]
+pub struct Config {
+    pub click_actions: Option<Vec<ClickAction>>,
+    pub click_handler: Option<String>,
+    pub restart: Option<bool>,
+    /// The programs to wrap instead of i3status, whose blocks are shown in
+    /// the order they are listed in.
+    pub upstreams: Option<Vec<Upstream>>,
+    /// Run without i3status, showing only the blocks of the modules.
+    pub standalone: Option<bool>,
+    /// Seconds between the status lines written in standalone mode.
+    pub interval: Option<u64>,
+    /// The least number of milliseconds between two status lines.
+    pub min_interval_ms: Option<u64>,
+    /// Whether to leave out status lines that are the same as the one before.
+    pub dedup: Option<bool>,
+    /// Where to listen for commands, `$XDG_RUNTIME_DIR/r3status.sock` by
+    /// default. An empty path turns the control socket off.
+    pub control_socket: Option<String>,
+    pub notifications: Option<NotificationConfig>,
+    pub invalid_utf8: Option<Utf8Policy>,
+    pub header: Option<HeaderConfig>,
+    pub placements: Option<Vec<Placement>>,
+    pub filters: Option<Vec<Filter>>,
+    pub thresholds: Option<Vec<Threshold>>,
+}
+
+/// Overrides for the fields of the header that is sent to i3bar.
+#[derive(Clone, Debug, Default, PartialEq, RustcDecodable)]
+pub struct HeaderConfig {
+    /// Set to `false` to leave the click events to i3bar.
+    pub click_events: Option<bool>,
+    pub stop_signal: Option<usize>,
+    pub cont_signal: Option<usize>,
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Config> {
+        let path = path.as_ref();
+        let mut contents = String::new();
+
+        try!(File::open(path)
+             .and_then(|mut f| f.read_to_string(&mut contents))
+             .map_err(|e| {
+                 Error::new(e.kind(), format!("failed to read config `{}`: {}", path.display(), e))
+             }));
+
+        json::decode(&contents).map_err(|e| {
+            Error::new(ErrorKind::InvalidData, format!("invalid config `{}`: {}", path.display(), e))
+        })
+    }
+}
+
+impl HeaderConfig {
+    /// Sets the fields of `header` that r3status takes care of itself, where
+    /// the ones that have not been configured get r3status' defaults.
+    pub fn apply(&self, header: &mut Header) {
+        header.version = PROTOCOL_VERSION;
+        header.click_events = Some(self.click_events());
+        header.stop_signal = Some(self.stop_signal() as usize);
+        header.cont_signal = Some(self.cont_signal() as usize);
+    }
+
+    pub fn click_events(&self) -> bool {
+        self.click_events.unwrap_or(true)
+    }
+
+    pub fn stop_signal(&self) -> c_int {
+        self.stop_signal.map_or(signal::STOP_SIGNAL, |sig| sig as c_int)
+    }
+
+    pub fn cont_signal(&self) -> c_int {
+        self.cont_signal.map_or(signal::CONT_SIGNAL, |sig| sig as c_int)
+    }
+}
+
+#[test]
+fn test_decode_config() {
+    let config: Config = json::decode("{}").unwrap();
+    assert!(config.click_actions.is_none());
+
+    let config: Config = json::decode(r#"{ "invalid_utf8": "drop" }"#).unwrap();
+    assert_eq!(Some(Utf8Policy::DropBlock), config.invalid_utf8);
+    assert!(json::decode::<Config>(r#"{ "invalid_utf8": "ignore" }"#).is_err());
+
+    let config: Config = json::decode(r#"{ "header": { "click_events": false } }"#).unwrap();
+    assert_eq!(Some(false), config.header.unwrap().click_events);
+
+    let config: Config = json::decode(r#"{
+        "click_actions": [
+            { "name": "volume", "button": 1, "command": "pavucontrol" },
+            { "name": "volume", "instance": "default.Master.0", "button": 4,
+              "command": "pactl set-sink-volume @DEFAULT_SINK@ +5%" }
+        ]
+    }"#).unwrap();
+    let actions = config.click_actions.unwrap();
+    assert_eq!(2, actions.len());
+    assert_eq!(None, actions[0].instance);
+    assert_eq!(Some("default.Master.0".to_string()), actions[1].instance);
+    assert_eq!(4, actions[1].button);
+}
@@ -0,0 +1,252 @@
+use rustc_serialize::json::{self, Json};
+use rustc_serialize::{Decodable, Decoder};
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use event::Event;
+use notify::Notification;
+
+/// A command sent to r3status through its control socket, as one line of
+/// JSON like `{ "command": "set_text", "name": "ci", "text": "passed" }`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Refreshes the modules and the upstreams, like `SIGUSR1`.
+    Refresh,
+    /// Replaces the `full_text` of a block, or puts it back without a `text`.
+    SetText { name: String, instance: Option<String>, text: Option<String> },
+    /// Shows a block for a while, decoded from the `text`, `color`, `urgent`
+    /// and `duration` of the command.
+    Notify(Notification),
+    /// Leaves the blocks of a module, or the blocks with a name, out of the bar.
+    Hide(String),
+    Show(String),
+    /// Reads the r3status config file again.
+    Reload,
+    /// Replies with the current status line.
+    Dump,
+}
+
+impl Decodable for Command {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Command, D::Error> {
+        d.read_struct("Command", 8, |d| {
+            let command: String = try!(d.read_struct_field("command", 0, Decodable::decode));
+            let name: Option<String> = try!(d.read_struct_field("name", 1, Decodable::decode));
+            let instance = try!(d.read_struct_field("instance", 2, Decodable::decode));
+            let text: Option<String> = try!(d.read_struct_field("text", 3, Decodable::decode));
+            let color = try!(d.read_struct_field("color", 4, Decodable::decode));
+            let urgent = try!(d.read_struct_field("urgent", 5, Decodable::decode));
+            let duration = try!(d.read_struct_field("duration", 6, Decodable::decode));
+            let module: Option<String> = try!(d.read_struct_field("module", 7, Decodable::decode));
+
+            match (&command[..], name, text, module) {
+                ("refresh", _, _, _) => Ok(Command::Refresh),
+                ("set_text", Some(name), text, _) => {
+                    Ok(Command::SetText { name: name, instance: instance, text: text })
+                }
+                ("notify", _, Some(text), _) => Ok(Command::Notify(Notification {
+                    text: text,
+                    color: color,
+                    urgent: urgent,
+                    duration: duration,
+                })),
+                ("hide", _, _, Some(module)) => Ok(Command::Hide(module)),
+                ("show", _, _, Some(module)) => Ok(Command::Show(module)),
+                ("reload", _, _, _) => Ok(Command::Reload),
+                ("dump", _, _, _) => Ok(Command::Dump),
+                ("set_text", ..) => Err(d.error("`set_text` needs a `name`")),
+                ("notify", ..) => Err(d.error("`notify` needs a `text`")),
+                ("hide", ..) | ("show", ..) => Err(d.error(&format!("`{}` needs a `module`", command))),
+                (other, ..) => Err(d.error(&format!("unknown command `{}`", other))),
+            }
+        })
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/r3status.sock`, where the control socket is by default.
+pub fn default_socket_path() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("r3status.sock"))
+}
+
+/// A successful reply, with the fields of `fields`.
+pub fn ok_reply(fields: BTreeMap<String, Json>) -> Json {
+    let mut fields = fields;
+    fields.insert("ok".to_string(), Json::Boolean(true));
+    Json::Object(fields)
+}
+
+pub fn error_reply(error: &str) -> Json {
+    let mut fields = BTreeMap::new();
+    fields.insert("ok".to_string(), Json::Boolean(false));
+    fields.insert("error".to_string(), Json::String(error.to_string()));
+    Json::Object(fields)
+}
+
+/// Listens for connections on `path` on a separate thread, and sends the
+/// commands that arrive to `events`, each with a channel for the reply.
+/// A socket that is left over from an earlier r3status is replaced, but
+/// anything else at `path` is left alone.
+pub fn spawn_listener(path: &Path, events: Sender<Event>) -> io::Result<()> {
+    if UnixStream::connect(path).is_ok() {
+        return Err(Error::new(ErrorKind::AddrInUse,
+                              format!("`{}` is in use by another r3status", path.display())));
+    }
+    match fs::symlink_metadata(path) {
+        Ok(ref meta) if !meta.file_type().is_socket() => return Err(Error::new(
+            ErrorKind::AlreadyExists, format!("`{}` exists and is not a socket", path.display()))),
+        Ok(_) => try!(fs::remove_file(path).map_err(|e| Error::new(e.kind(), format!(
+            "failed to remove the old socket `{}`: {}", path.display(), e)))),
+        Err(_) => (),
+    }
+    let listener = try!(UnixListener::bind(path).map_err(|e| {
+        Error::new(e.kind(), format!("failed to listen on `{}`: {}", path.display(), e))
+    }));
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Ok(stream) = stream {
+                let events = events.clone();
+                thread::spawn(move || serve(stream, events));
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Answers the commands of one connection, one line each.
+fn serve(stream: UnixStream, events: Sender<Event>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(ref line) if line.trim().is_empty() => continue,
+            Ok(line) => line,
+            Err(_) => return,
+        };
+
+        let reply = match json::decode(&line) {
+            Ok(command) => {
+                let (tx, rx) = mpsc::channel();
+                if events.send(Event::Control(command, tx)).is_err() {
+                    return;
+                }
+                match rx.recv() {
+                    Ok(reply) => reply,
+                    Err(_) => return,
+                }
+            }
+            Err(e) => error_reply(&format!("invalid command `{}`: {}", line, e)),
+        };
+        if writeln!(writer, "{}", reply).is_err() {
+            return;
+        }
+    }
+}
+
+/// The fields of the commands that are not strings.
+const NON_STRING_FIELDS: &'static [&'static str] = &["duration", "urgent"];
+
+/// Turns `words` like `notify text="build failed" duration=10` into a
+/// command. Values are taken as strings, except for the fields in
+/// `NON_STRING_FIELDS`, which are taken as JSON.
+pub fn encode_command(words: &[String]) -> Result<String, String> {
+    let (command, fields) = match words.split_first() {
+        Some(split) => split,
+        None => return Err("missing command".to_string()),
+    };
+    if command.starts_with("{") {
+        return Ok(command.clone());
+    }
+
+    let mut object = BTreeMap::new();
+    object.insert("command".to_string(), Json::String(command.clone()));
+    for field in fields {
+        let mut parts = field.splitn(2, '=');
+        let key = parts.next().unwrap_or("");
+        let value = match parts.next() {
+            Some(value) => value,
+            None => return Err(format!("expected `<key>=<value>`, got `{}`", field)),
+        };
+        let value = if NON_STRING_FIELDS.contains(&key) {
+            try!(Json::from_str(value).map_err(|_| format!("invalid value for `{}`: `{}`", key, value)))
+        } else {
+            Json::String(value.to_string())
+        };
+        object.insert(key.to_string(), value);
+    }
+    Ok(Json::Object(object).to_string())
+}
+
+/// Sends `command` to the r3status that listens on `path`, and returns its
+/// reply. A reply that says the command failed is returned as an error.
+pub fn send_command(path: &Path, command: &str) -> io::Result<String> {
+    let mut stream = try!(UnixStream::connect(path).map_err(|e| {
+        Error::new(e.kind(), format!("failed to connect to `{}`: {}", path.display(), e))
+    }));
+    try!(writeln!(stream, "{}", command));
+
+    let mut reply = String::new();
+    try!(BufReader::new(stream).read_line(&mut reply));
+
+    let json = try!(Json::from_str(&reply).map_err(|e| Error::new(ErrorKind::InvalidData, e)));
+    match json.find("ok").and_then(|ok| ok.as_boolean()) {
+        Some(true) => Ok(reply.trim().to_string()),
+        _ => {
+            let error = json.find("error").and_then(|e| e.as_string()).unwrap_or("no reply");
+            Err(Error::new(ErrorKind::Other, error.to_string()))
+        }
+    }
+}
+
+#[test]
+fn test_decode_command() {
+    assert_eq!(Command::Refresh, json::decode(r#"{"command":"refresh"}"#).unwrap());
+    assert_eq!(Command::SetText { name: "ci".to_string(), instance: None, text: None },
+               json::decode(r#"{"command":"set_text","name":"ci"}"#).unwrap());
+    assert_eq!(Command::Hide("clock".to_string()),
+               json::decode(r#"{"command":"hide","module":"clock"}"#).unwrap());
+
+    let notify: Command = json::decode(r#"{"command":"notify","text":"hi","duration":2}"#).unwrap();
+    match notify {
+        Command::Notify(n) => assert_eq!((Some(2), None), (n.duration, n.urgent)),
+        other => panic!("expected a notification, got {:?}", other),
+    }
+
+    assert!(json::decode::<Command>(r#"{"command":"notify"}"#).is_err());
+    assert!(json::decode::<Command>(r#"{"command":"restart"}"#).is_err());
+}
+
+#[test]
+fn test_encode_command() {
+    let words: Vec<String> = vec!["notify", "text=build failed", "urgent=true", "duration=10"]
+        .into_iter().map(String::from).collect();
+    assert_eq!(r#"{"command":"notify","duration":10,"text":"build failed","urgent":true}"#,
+               encode_command(&words).unwrap());
+    let words: Vec<String> = vec!["set_text", "name=cpu", "text=42"].into_iter().map(String::from).collect();
+    assert_eq!(r#"{"command":"set_text","name":"cpu","text":"42"}"#, encode_command(&words).unwrap());
+    assert!(encode_command(&["notify".to_string(), "text".to_string()]).is_err());
+    assert!(encode_command(&["notify".to_string(), "duration=soon".to_string()]).is_err());
+    assert!(encode_command(&[]).is_err());
+}
+
+#[test]
+fn test_spawn_listener_keeps_other_files() {
+    let path = env::temp_dir().join(format!("r3status-test-{}.sock", ::std::process::id()));
+    fs::File::create(&path).unwrap();
+
+    let (tx, _rx) = mpsc::channel();
+    let e = spawn_listener(&path, tx).unwrap_err();
+    assert_eq!(ErrorKind::AlreadyExists, e.kind());
+    assert!(path.is_file());
+    fs::remove_file(&path).unwrap();
+}
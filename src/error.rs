@@ -0,0 +1,66 @@
+use rustc_serialize::json;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+
+#[derive(Debug)]
+pub enum R3Error {
+    Io(io::Error),
+    /// A line from the upstream that could not be decoded, along with the line.
+    Json { line: String, error: json::DecoderError },
+    Encode(json::EncoderError),
+    /// The upstream sent something that does not follow the i3bar protocol.
+    Protocol(String),
+    /// The upstream, i3status by default, could not be spawned or talked to.
+    Child(String),
+    /// The upstream closed its stdout, which it only does when it exits. Holds
+    /// its exit status, once it has been collected.
+    Eof(Option<ExitStatus>),
+}
+
+pub type R3Result<T> = Result<T, R3Error>;
+
+impl R3Error {
+    pub fn json(line: &str, error: json::DecoderError) -> R3Error {
+        R3Error::Json { line: line.trim().to_string(), error: error }
+    }
+}
+
+impl fmt::Display for R3Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            R3Error::Io(ref e) => write!(f, "{}", e),
+            R3Error::Json { ref line, ref error } => {
+                write!(f, "failed to decode `{}`: {}", line, error)
+            }
+            R3Error::Encode(ref e) => write!(f, "failed to encode: {}", e),
+            R3Error::Protocol(ref msg) => write!(f, "protocol violation: {}", msg),
+            R3Error::Child(ref msg) => write!(f, "{}", msg),
+            R3Error::Eof(Some(status)) => write!(f, "upstream exited ({})", status),
+            R3Error::Eof(None) => write!(f, "upstream closed its stdout"),
+        }
+    }
+}
+
+impl error::Error for R3Error {}
+
+impl From<io::Error> for R3Error {
+    fn from(e: io::Error) -> R3Error {
+        R3Error::Io(e)
+    }
+}
+
+impl From<json::EncoderError> for R3Error {
+    fn from(e: json::EncoderError) -> R3Error {
+        R3Error::Encode(e)
+    }
+}
+
+#[test]
+fn test_display() {
+    let e = R3Error::json(",[{\"full_text\":}]\n", json::decode::<usize>("").unwrap_err());
+    assert!(e.to_string().starts_with("failed to decode `,[{\"full_text\":}]`: "));
+    assert_eq!("upstream closed its stdout", R3Error::Eof(None).to_string());
+}
@@ -0,0 +1,34 @@
+use libc::c_int;
+use rustc_serialize::json::Json;
+
+use std::sync::mpsc::Sender;
+
+use control::Command;
+use {Block, ClickEvent, Header, R3Error};
+
+/// Everything that the main loop of r3status waits for, sent from the
+/// threads that watch the upstreams, i3bar and the signals. Events about an
+/// upstream come with the index of the upstream.
+#[derive(Debug)]
+pub enum Event {
+    /// The header of an upstream, which is only sent once.
+    Header(usize, Header),
+    Line(usize, Vec<Block>),
+    /// Shown in place of the blocks of the upstream, until its next line.
+    Error(usize, R3Error),
+    /// Shown in place of the blocks of the upstream, like that it is being
+    /// restarted.
+    Message(usize, String),
+    /// An upstream has stopped, and is not to be restarted. When it exited
+    /// by itself, its exit status is in `R3Error::Eof`.
+    Exit(R3Error),
+    /// A click on one of the blocks, sent by i3bar.
+    Click(ClickEvent),
+    /// A line from the click handler, answering the oldest click that it has
+    /// not answered yet.
+    ClickReply(String),
+    /// One of the signals that r3status handles has arrived.
+    Signal(c_int),
+    /// A command from the control socket, and where to send the reply.
+    Control(Command, Sender<Json>),
+}
@@ -0,0 +1,153 @@
+use regex::Regex;
+use rustc_serialize::{Decodable, Decoder};
+
+use Block;
+
+/// A rewrite of the blocks that match on `name`, `instance` and a regex on
+/// `full_text`, where every unset criterion matches any block.
+///
+/// Decoded from an object like
+/// `{ "name": "wireless", "replace": "^W: \\((.*)\\) .*$", "with": "📶 $1" }`,
+/// where `replace`, `with`, `prefix`, `suffix`, `short_text`, `color`,
+/// `urgent` and `hide` are all optional.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    pub name: Option<String>,
+    pub instance: Option<String>,
+    pub matches: Option<Regex>,
+    /// A regex and its replacement, which may refer to captures like `$1`.
+    pub replace: Option<(Regex, String)>,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub short_text: Option<String>,
+    pub color: Option<String>,
+    pub urgent: Option<bool>,
+    pub hide: bool,
+}
+
+impl Filter {
+    pub fn matches(&self, block: &Block) -> bool {
+        (self.name.is_none() || self.name == block.name)
+            && (self.instance.is_none() || self.instance == block.instance)
+            && self.matches.as_ref().map_or(true, |re| re.is_match(&block.full_text))
+    }
+
+    /// Rewrites `block`, and returns whether it is to be kept.
+    pub fn apply(&self, block: &mut Block) -> bool {
+        if self.hide {
+            return false;
+        }
+
+        if let Some((ref re, ref with)) = self.replace {
+            block.full_text = re.replace_all(&block.full_text, &with[..]).into_owned();
+        }
+        if let Some(ref prefix) = self.prefix {
+            block.full_text.insert_str(0, prefix);
+        }
+        if let Some(ref suffix) = self.suffix {
+            block.full_text.push_str(suffix);
+        }
+        if self.short_text.is_some() {
+            block.short_text = self.short_text.clone();
+        }
+        if self.color.is_some() {
+            block.color = self.color.clone();
+        }
+        if self.urgent.is_some() {
+            block.urgent = self.urgent;
+        }
+        true
+    }
+}
+
+impl Decodable for Filter {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Filter, D::Error> {
+        d.read_struct("Filter", 11, |d| {
+            let name = try!(d.read_struct_field("name", 0, Decodable::decode));
+            let instance = try!(d.read_struct_field("instance", 1, Decodable::decode));
+            let matches: Option<String> = try!(d.read_struct_field("matches", 2, Decodable::decode));
+            let replace: Option<String> = try!(d.read_struct_field("replace", 3, Decodable::decode));
+            let with: Option<String> = try!(d.read_struct_field("with", 4, Decodable::decode));
+            let hide: Option<bool> = try!(d.read_struct_field("hide", 10, Decodable::decode));
+
+            let matches = match matches {
+                Some(re) => Some(try!(compile(d, &re))),
+                None => None,
+            };
+            let replace = match replace {
+                Some(re) => Some((try!(compile(d, &re)), with.unwrap_or_default())),
+                None if with.is_some() => return Err(d.error("`with` needs a `replace` regex")),
+                None => None,
+            };
+
+            Ok(Filter {
+                name: name,
+                instance: instance,
+                matches: matches,
+                replace: replace,
+                prefix: try!(d.read_struct_field("prefix", 5, Decodable::decode)),
+                suffix: try!(d.read_struct_field("suffix", 6, Decodable::decode)),
+                short_text: try!(d.read_struct_field("short_text", 7, Decodable::decode)),
+                color: try!(d.read_struct_field("color", 8, Decodable::decode)),
+                urgent: try!(d.read_struct_field("urgent", 9, Decodable::decode)),
+                hide: hide.unwrap_or(false),
+            })
+        })
+    }
+}
+
+fn compile<D: Decoder>(d: &mut D, re: &str) -> Result<Regex, D::Error> {
+    Regex::new(re).map_err(|e| d.error(&format!("invalid regex `{}`: {}", re, e)))
+}
+
+/// Runs `blocks` through every filter that matches them, in order.
+pub fn apply_all(filters: &[Filter], blocks: &mut Vec<Block>) {
+    let mut kept = Vec::with_capacity(blocks.len());
+
+    'blocks: for mut block in blocks.drain(..) {
+        for filter in filters {
+            if filter.matches(&block) && !filter.apply(&mut block) {
+                continue 'blocks;
+            }
+        }
+        kept.push(block);
+    }
+    *blocks = kept;
+}
+
+#[test]
+fn test_filters() {
+    use rustc_serialize::json;
+
+    let filters: Vec<Filter> = json::decode(r##"[
+        { "name": "wireless", "replace": "^W: \\([^)]*\\) ([0-9.]+)$", "with": "W: $1" },
+        { "name": "wireless", "prefix": "[", "suffix": "]", "short_text": "W" },
+        { "name": "disk_info", "matches": "^[0-9.]+ MiB$", "color": "#FF0000", "urgent": true },
+        { "name": "ethernet", "instance": "eth1", "hide": true }
+    ]"##).unwrap();
+
+    let block = |name: &str, instance: &str, text: &str| Block {
+        full_text: text.to_string(),
+        name: Some(name.to_string()),
+        instance: Some(instance.to_string()),
+        .. Default::default()
+    };
+    let mut blocks = vec![
+        block("wireless", "wlan0", "W: (70% at home) 192.168.1.2"),
+        block("disk_info", "/", "512.0 MiB"),
+        block("disk_info", "/home", "20.1 GiB"),
+        block("ethernet", "eth0", "E: down"),
+        block("ethernet", "eth1", "E: down"),
+    ];
+    apply_all(&filters, &mut blocks);
+
+    assert_eq!(4, blocks.len());
+    assert_eq!("[W: 192.168.1.2]", blocks[0].full_text);
+    assert_eq!(Some("W".to_string()), blocks[0].short_text);
+    assert_eq!((Some("#FF0000".to_string()), Some(true)), (blocks[1].color.clone(), blocks[1].urgent));
+    assert_eq!((None, None), (blocks[2].color.clone(), blocks[2].urgent));
+    assert_eq!(Some("eth0".to_string()), blocks[3].instance);
+
+    assert!(json::decode::<Filter>(r#"{ "matches": "(" }"#).is_err());
+    assert!(json::decode::<Filter>(r#"{ "with": "x" }"#).is_err());
+}
@@ -1,11 +1,64 @@
+extern crate libc;
+extern crate regex;
 extern crate rustc_serialize;
+
+mod click;
+mod config;
+mod control;
+mod error;
+mod event;
+mod filter;
+mod module;
+mod notify;
+mod placement;
+mod signal;
+mod threshold;
+mod upstream;
+mod utf8;
+
+pub use click::{ClickAction, ClickEvent, ClickHandler};
+pub use config::{Config, HeaderConfig};
+pub use control::{default_socket_path, encode_command, send_command, Command};
+pub use error::{R3Error, R3Result};
+pub use event::Event;
+pub use filter::Filter;
+pub use module::Module;
+pub use notify::{Notification, NotificationConfig};
+pub use placement::{Anchor, Placement, Position};
+pub use threshold::{Level, Threshold};
+pub use upstream::Upstream;
+pub use utf8::Utf8Policy;
+
 use rustc_serialize::{json, Decodable, Decoder, Encodable, Encoder};
+use rustc_serialize::json::{DecoderError, Json, ToJson};
+
+use libc::c_int;
+
+use std::collections::{BTreeMap, BTreeSet};
 
-use std::path::Path;
-use std::io::{self, Error, ErrorKind, LineWriter, Write, BufRead, BufReader};
-use std::process::{self, Command, Child, ChildStdout};
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::io::{self, LineWriter, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 
-#[derive(Debug, PartialEq)]
+use module::Modules;
+use notify::Notifications;
+use placement::Placements;
+use threshold::Thresholds;
+use std::time::{Duration, Instant};
+
+/// The color of the block that errors are shown in.
+const ERROR_COLOR: &'static str = "#FF0000";
+
+/// How often a status line is written in standalone mode, by default. The
+/// same as i3status' own default.
+const DEFAULT_INTERVAL_SECS: u64 = 5;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Alignment {
     Right,
     Left,
@@ -33,27 +86,88 @@ impl Encodable for Alignment {
     }
 }
 
+impl ToJson for Alignment {
+    fn to_json(&self) -> Json {
+        Json::String(match *self {
+            Alignment::Right => "right",
+            Alignment::Left => "left",
+            Alignment::Center => "center",
+        }.to_string())
+    }
+}
+
+/// The minimum width of a block, either in pixels or as the width of a
+/// sample text.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MinWidth {
+    Pixels(usize),
+    Text(String),
+}
 
-#[derive(Debug, RustcDecodable, RustcEncodable)]
+impl MinWidth {
+    pub fn from_json(json: &Json) -> Result<MinWidth, DecoderError> {
+        match *json {
+            Json::String(ref text) => Ok(MinWidth::Text(text.clone())),
+            Json::U64(px) => Ok(MinWidth::Pixels(px as usize)),
+            Json::I64(px) if px >= 0 => Ok(MinWidth::Pixels(px as usize)),
+            ref other => Err(DecoderError::ExpectedError("String or Number".to_string(),
+                                                         other.to_string())),
+        }
+    }
+}
+
+impl ToJson for MinWidth {
+    fn to_json(&self) -> Json {
+        match *self {
+            MinWidth::Pixels(px) => px.to_json(),
+            MinWidth::Text(ref text) => text.to_json(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Block {
     pub full_text: String,
     pub short_text: Option<String>,
     pub color: Option<String>,
-    pub min_width: Option<usize>,
+    pub background: Option<String>,
+    pub border: Option<String>,
+    pub border_top: Option<usize>,
+    pub border_right: Option<usize>,
+    pub border_bottom: Option<usize>,
+    pub border_left: Option<usize>,
+    pub min_width: Option<MinWidth>,
     pub align: Option<Alignment>,
     pub urgent: Option<bool>,
     pub name: Option<String>,
     pub instance: Option<String>,
     pub separator: Option<bool>,
     pub separator_block_width: Option<usize>,
+    pub markup: Option<String>,
+    /// Fields that r3status does not know about, like swaybar's `_`-prefixed
+    /// custom fields, which are passed on to i3bar as they are.
+    pub extra: BTreeMap<String, Json>,
 }
 
+/// The fields of `Block` that are decoded into their own members.
+const BLOCK_FIELDS: &'static [&'static str] = &[
+    "full_text", "short_text", "color", "background", "border", "border_top", "border_right",
+    "border_bottom", "border_left", "min_width", "align", "urgent", "name", "instance",
+    "separator", "separator_block_width", "markup",
+];
+
 impl Default for Block {
     fn default() -> Block {
         Block {
             full_text: String::new(),
             short_text: None,
             color: None,
+            background: None,
+            border: None,
+            border_top: None,
+            border_right: None,
+            border_bottom: None,
+            border_left: None,
             min_width: None,
             align: None,
             urgent: None,
@@ -61,45 +175,251 @@ impl Default for Block {
             instance: None,
             separator: None,
             separator_block_width: None,
+            markup: None,
+            extra: BTreeMap::new(),
         }
     }
 }
 
-#[derive(Debug, RustcDecodable, RustcEncodable)]
+impl ToJson for Block {
+    fn to_json(&self) -> Json {
+        let mut obj = self.extra.clone();
+        obj.insert("full_text".to_string(), self.full_text.to_json());
+        insert_some(&mut obj, "short_text", &self.short_text);
+        insert_some(&mut obj, "color", &self.color);
+        insert_some(&mut obj, "background", &self.background);
+        insert_some(&mut obj, "border", &self.border);
+        insert_some(&mut obj, "border_top", &self.border_top);
+        insert_some(&mut obj, "border_right", &self.border_right);
+        insert_some(&mut obj, "border_bottom", &self.border_bottom);
+        insert_some(&mut obj, "border_left", &self.border_left);
+        insert_some(&mut obj, "min_width", &self.min_width);
+        insert_some(&mut obj, "align", &self.align);
+        insert_some(&mut obj, "urgent", &self.urgent);
+        insert_some(&mut obj, "name", &self.name);
+        insert_some(&mut obj, "instance", &self.instance);
+        insert_some(&mut obj, "separator", &self.separator);
+        insert_some(&mut obj, "separator_block_width", &self.separator_block_width);
+        insert_some(&mut obj, "markup", &self.markup);
+        Json::Object(obj)
+    }
+}
+
+// Blocks are encoded through `ToJson` so that unset fields are left out
+// entirely, instead of being sent to i3bar as `null`.
+impl Encodable for Block {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        self.to_json().encode(e)
+    }
+}
+
+impl Block {
+    /// Decodes a block from one of the objects in a status line. This is done
+    /// from `Json` rather than through `Decodable`, as `min_width` can hold
+    /// either a number or a string.
+    pub fn from_json(json: &Json) -> Result<Block, DecoderError> {
+        let obj = try!(json.as_object().ok_or_else(|| {
+            DecoderError::ExpectedError("Object".to_string(), json.to_string())
+        }));
+        let full_text = try!(try!(decode_field(obj, "full_text")).ok_or_else(|| {
+            DecoderError::MissingFieldError("full_text".to_string())
+        }));
+        let min_width = match obj.get("min_width") {
+            None | Some(&Json::Null) => None,
+            Some(json) => Some(try!(MinWidth::from_json(json))),
+        };
+        let extra = obj.iter()
+            .filter(|&(key, _)| !BLOCK_FIELDS.contains(&&key[..]))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        Ok(Block {
+            full_text: full_text,
+            short_text: try!(decode_field(obj, "short_text")),
+            color: try!(decode_field(obj, "color")),
+            background: try!(decode_field(obj, "background")),
+            border: try!(decode_field(obj, "border")),
+            border_top: try!(decode_field(obj, "border_top")),
+            border_right: try!(decode_field(obj, "border_right")),
+            border_bottom: try!(decode_field(obj, "border_bottom")),
+            border_left: try!(decode_field(obj, "border_left")),
+            min_width: min_width,
+            align: try!(decode_field(obj, "align")),
+            urgent: try!(decode_field(obj, "urgent")),
+            name: try!(decode_field(obj, "name")),
+            instance: try!(decode_field(obj, "instance")),
+            separator: try!(decode_field(obj, "separator")),
+            separator_block_width: try!(decode_field(obj, "separator_block_width")),
+            markup: try!(decode_field(obj, "markup")),
+            extra: extra,
+        })
+    }
+}
+
+fn insert_some<T: ToJson>(obj: &mut json::Object, key: &str, value: &Option<T>) {
+    if let Some(ref value) = *value {
+        obj.insert(key.to_string(), value.to_json());
+    }
+}
+
+/// Decodes the field `key` of `obj`, where both a missing field and `null`
+/// are treated as unset.
+fn decode_field<T: Decodable>(obj: &json::Object, key: &str) -> Result<Option<T>, DecoderError> {
+    match obj.get(key) {
+        None | Some(&Json::Null) => Ok(None),
+        Some(value) => Decodable::decode(&mut json::Decoder::new(value.clone())).map(Some),
+    }
+}
+
+/// The version of the i3bar protocol that r3status speaks.
+const PROTOCOL_VERSION: usize = 1;
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Header {
-    version: usize,
-    stop_signal: Option<usize>,
-    cont_signal: Option<usize>,
-    click_events: Option<bool>,
+    pub version: usize,
+    pub stop_signal: Option<usize>,
+    pub cont_signal: Option<usize>,
+    pub click_events: Option<bool>,
+    /// Fields that r3status does not know about, passed on as they are.
+    pub extra: BTreeMap<String, Json>,
 }
 
+const HEADER_FIELDS: &'static [&'static str] = &[
+    "version", "stop_signal", "cont_signal", "click_events",
+];
 
 impl Default for Header {
     fn default() -> Header {
         Header {
-            version: 1,
+            version: PROTOCOL_VERSION,
             stop_signal: None,
             cont_signal: None,
             click_events: None,
+            extra: BTreeMap::new(),
         }
     }
 }
+
+impl Header {
+    pub fn from_json(json: &Json) -> Result<Header, DecoderError> {
+        let obj = try!(json.as_object().ok_or_else(|| {
+            DecoderError::ExpectedError("Object".to_string(), json.to_string())
+        }));
+        let version = try!(try!(decode_field(obj, "version")).ok_or_else(|| {
+            DecoderError::MissingFieldError("version".to_string())
+        }));
+        let extra = obj.iter()
+            .filter(|&(key, _)| !HEADER_FIELDS.contains(&&key[..]))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        Ok(Header {
+            version: version,
+            stop_signal: try!(decode_field(obj, "stop_signal")),
+            cont_signal: try!(decode_field(obj, "cont_signal")),
+            click_events: try!(decode_field(obj, "click_events")),
+            extra: extra,
+        })
+    }
+}
+
+impl ToJson for Header {
+    fn to_json(&self) -> Json {
+        let mut obj = self.extra.clone();
+        obj.insert("version".to_string(), self.version.to_json());
+        insert_some(&mut obj, "stop_signal", &self.stop_signal);
+        insert_some(&mut obj, "cont_signal", &self.cont_signal);
+        insert_some(&mut obj, "click_events", &self.click_events);
+        Json::Object(obj)
+    }
+}
+
+impl Encodable for Header {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        self.to_json().encode(e)
+    }
+}
+
 pub struct R3Status {
     config_file: Option<String>,
+    r3_config_file: Option<PathBuf>,
+    control_socket: Option<PathBuf>,
+    listening_on: Option<PathBuf>,
+    upstreams: Vec<Upstream>,
+    upstream_pids: Vec<Arc<AtomicUsize>>,
+    upstream_lines: Vec<Vec<Block>>,
     status_line: Vec<Block>,
-    reader: Option<BufReader<ChildStdout>>,
+    modules: Modules,
+    placements: Placements,
+    filters: Vec<Filter>,
+    thresholds: Thresholds,
+    hidden: BTreeSet<String>,
+    notifications: Notifications,
+    restart: bool,
+    standalone: bool,
+    interval: Duration,
+    min_interval: Duration,
+    dedup: bool,
+    last_write: Option<Instant>,
+    last_line: String,
+    /// Set when the bar is shown again, to write the next line even if it is
+    /// the same as the last one.
+    force_write: bool,
     writer: LineWriter<io::Stdout>,
     buffer: String,
+    utf8_policy: Utf8Policy,
+    invalid_utf8: Arc<AtomicUsize>,
+    header_config: HeaderConfig,
+    header_sent: bool,
+    array_started: bool,
+    first_line_sent: bool,
+    click_actions: Vec<ClickAction>,
+    click_handler_cmd: Option<String>,
+    click_handler: Option<ClickHandler>,
+    full_text_overrides: BTreeMap<(Option<String>, Option<String>), String>,
+    on_click: Option<Box<dyn FnMut(&ClickEvent) + Send>>,
+    paused: Arc<AtomicBool>,
 }
 
 impl R3Status {
     pub fn new() -> R3Status {
         R3Status {
             config_file: None,
+            r3_config_file: None,
+            control_socket: default_socket_path(),
+            listening_on: None,
+            upstreams: vec![Upstream::default()],
+            upstream_pids: Vec::new(),
+            upstream_lines: Vec::new(),
             status_line: Vec::new(),
-            reader: None,
+            modules: Modules::default(),
+            placements: Placements::default(),
+            filters: Vec::new(),
+            thresholds: Thresholds::default(),
+            hidden: BTreeSet::new(),
+            notifications: Notifications::default(),
+            restart: true,
+            standalone: false,
+            interval: Duration::from_secs(DEFAULT_INTERVAL_SECS),
+            min_interval: Duration::from_secs(0),
+            dedup: true,
+            last_write: None,
+            last_line: String::new(),
+            force_write: false,
             writer: LineWriter::new(io::stdout()),
             buffer: String::new(),
+            utf8_policy: Utf8Policy::default(),
+            invalid_utf8: Arc::new(AtomicUsize::new(0)),
+            header_config: HeaderConfig::default(),
+            header_sent: false,
+            array_started: false,
+            first_line_sent: false,
+            click_actions: Vec::new(),
+            click_handler_cmd: None,
+            click_handler: None,
+            full_text_overrides: BTreeMap::new(),
+            on_click: None,
+            paused: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -107,94 +427,717 @@ impl R3Status {
         self.config_file = Some(config.to_string());
     }
 
-    pub fn clear(&mut self) {
-        self.buffer.clear()
+    /// Wraps `upstream` instead of i3status, replacing any other upstreams.
+    pub fn upstream(&mut self, upstream: Upstream) {
+        self.upstreams = vec![upstream];
+    }
+
+    /// Adds an upstream, whose blocks go after the ones of the upstreams that
+    /// were added before it, starting with i3status.
+    pub fn add_upstream(&mut self, upstream: Upstream) {
+        self.upstreams.push(upstream);
+    }
+
+    /// How to handle output from the upstreams that is not valid UTF-8.
+    pub fn utf8_policy(&mut self, policy: Utf8Policy) {
+        self.utf8_policy = policy;
+    }
+
+    /// The number of invalid UTF-8 sequences that the upstreams have sent so far.
+    pub fn invalid_utf8_count(&self) -> usize {
+        self.invalid_utf8.load(Ordering::SeqCst)
+    }
+
+    /// Overrides the fields of the header that r3status sends to i3bar.
+    pub fn header_config(&mut self, config: HeaderConfig) {
+        self.header_config = config;
+    }
+
+    /// Whether to restart the upstreams when they exit, instead of shutting down.
+    pub fn restart(&mut self, restart: bool) {
+        self.restart = restart;
+    }
+
+    /// Runs without i3status, with r3status writing the header and a status
+    /// line of module blocks every `interval` by itself.
+    pub fn standalone(&mut self, standalone: bool) {
+        self.standalone = standalone;
+    }
+
+    /// How often a status line is written in standalone mode.
+    pub fn interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// The least amount of time between two status lines. Whatever changes
+    /// in between is written all at once, when the time is up.
+    pub fn min_interval(&mut self, interval: Duration) {
+        self.min_interval = interval;
+    }
+
+    /// Whether to leave out status lines that are the same as the line
+    /// before them. On by default.
+    pub fn dedup(&mut self, dedup: bool) {
+        self.dedup = dedup;
+    }
+
+    /// Where to listen for commands, or `None` to not listen at all.
+    pub fn control_socket(&mut self, path: Option<PathBuf>) {
+        self.control_socket = path;
     }
 
-    pub fn read_line(&mut self) -> io::Result<usize> {
-        if let Some(reader) = self.reader.as_mut() {
-            // trust i3status to give us valid utf8 for now...
-            unsafe {
-                reader.read_until('\n' as u8, self.buffer.as_mut_vec())
+    /// Reads the r3status config file at `path` and applies it. The path is
+    /// remembered for the `reload` command.
+    pub fn load_config<P: AsRef<Path>>(&mut self, path: P) -> R3Result<()> {
+        let config = try!(Config::load(&path));
+        self.apply_config(config);
+        self.r3_config_file = Some(path.as_ref().to_path_buf());
+        Ok(())
+    }
+
+    pub fn apply_config(&mut self, config: Config) {
+        if let Some(actions) = config.click_actions {
+            self.click_actions = actions;
+        }
+        if config.click_handler.is_some() {
+            self.click_handler_cmd = config.click_handler;
+        }
+        if let Some(restart) = config.restart {
+            self.restart = restart;
+        }
+        if let Some(policy) = config.invalid_utf8 {
+            self.utf8_policy = policy;
+        }
+        if let Some(header) = config.header {
+            self.header_config = header;
+        }
+        if let Some(placements) = config.placements {
+            self.placements.clear();
+            for placement in placements {
+                self.placements.add(placement);
             }
-        } else {
-            Err(Error::new(ErrorKind::Other, "A reader has not been set for the process"))
         }
+        if let Some(upstreams) = config.upstreams {
+            self.upstreams = upstreams;
+        }
+        if let Some(standalone) = config.standalone {
+            self.standalone = standalone;
+        }
+        if let Some(interval) = config.interval {
+            self.interval = Duration::from_secs(interval);
+        }
+        if let Some(interval) = config.min_interval_ms {
+            self.min_interval = Duration::from_millis(interval);
+        }
+        if let Some(dedup) = config.dedup {
+            self.dedup = dedup;
+        }
+        if let Some(notifications) = config.notifications {
+            self.notifications.configure(notifications);
+        }
+        if let Some(path) = config.control_socket {
+            self.control_socket = if path.is_empty() { None } else { Some(PathBuf::from(path)) };
+        }
+        if let Some(filters) = config.filters {
+            self.filters = filters;
+        }
+        if let Some(thresholds) = config.thresholds {
+            self.thresholds.clear();
+            for threshold in thresholds {
+                self.thresholds.add(threshold);
+            }
+        }
+    }
+
+    /// Adds a shell command to run when a block is clicked.
+    pub fn click_action(&mut self, action: ClickAction) {
+        self.click_actions.push(action);
+    }
+
+    /// Sets a command that is kept running and sent every click event, see
+    /// `ClickHandler`.
+    pub fn click_handler(&mut self, command: &str) {
+        self.click_handler_cmd = Some(command.to_string());
+    }
+
+    /// Sets a callback that is invoked for every click event sent by i3bar.
+    pub fn on_click<F>(&mut self, f: F) where F: FnMut(&ClickEvent) + Send + 'static {
+        self.on_click = Some(Box::new(f));
     }
 
-    pub fn flush_buffer(&mut self) -> io::Result<()> {
+    /// Adds a module, whose blocks are shown after the ones from i3status,
+    /// unless it has been given a placement.
+    pub fn add_module<M: Module + 'static>(&mut self, module: M) {
+        self.modules.add(Box::new(module));
+    }
+
+    /// Sets where the blocks of a module go, replacing any earlier placement
+    /// of the same module.
+    pub fn place(&mut self, placement: Placement) {
+        self.placements.add(placement);
+    }
+
+    /// Adds a filter that rewrites the blocks it matches, after the filters
+    /// that were added before it.
+    pub fn add_filter(&mut self, filter: Filter) {
+        self.filters.push(filter);
+    }
+
+    /// Adds a rule that sets the color and urgency of blocks from the number
+    /// they show. Thresholds are applied before the filters.
+    pub fn add_threshold(&mut self, threshold: Threshold) {
+        self.thresholds.add(threshold);
+    }
+
+    /// Whether i3bar has hidden the bar and asked us to stop updating it.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// The blocks of the status line that is sent to i3bar, made up of the
+    /// last line received from i3status and the blocks of the modules.
+    pub fn status_line(&self) -> &[Block] {
+        &self.status_line
+    }
+
+    pub fn status_line_mut(&mut self) -> &mut Vec<Block> {
+        &mut self.status_line
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear()
+    }
+
+    pub fn flush_buffer(&mut self) -> R3Result<()> {
         if self.buffer.ends_with("\n") {
             try!(write!(self.writer, "{}", self.buffer))
         } else {
             try!(writeln!(self.writer, "{}", self.buffer))
         }
+        // Kept without the `,` in front, which only the first line lacks
+        self.last_line = self.buffer.trim().trim_start_matches(',').to_string();
         self.clear();
         Ok(())
     }
 
-    pub fn write_str(&mut self, line: &str) -> io::Result<()> {
-        self.writer.write_all(line.as_bytes())
+    pub fn write_str(&mut self, line: &str) -> R3Result<()> {
+        Ok(try!(self.writer.write_all(line.as_bytes())))
+    }
+
+    /// Shows `msg` as a notification, and writes the status line with it
+    /// right away if the bar has been started.
+    pub fn write_msg(&mut self, msg: &str) -> R3Result<()> {
+        self.notify(Notification::new(msg));
+        if !self.array_started {
+            return Ok(());
+        }
+        self.rebuild_status_line();
+        self.write_status_line(false)
+    }
+
+    /// Shows `notification` in the bar until it expires, after the other
+    /// blocks or in place of them, depending on the `NotificationConfig`.
+    pub fn notify(&mut self, notification: Notification) {
+        self.notifications.push(notification, Instant::now());
+    }
+
+    pub fn notification_config(&mut self, config: NotificationConfig) {
+        self.notifications.configure(config);
+    }
+
+    /// Shows `e` in the bar as an urgent block, on a line of its own.
+    pub fn write_error(&mut self, e: &R3Error) -> R3Result<()> {
+        try!(self.start_array());
+        let line = try!(encode_status_line(&[error_block(e)], self.first_line_sent));
+        self.write_status(line)
+    }
+
+    fn write_status(&mut self, line: String) -> R3Result<()> {
+        self.buffer = line;
+        self.first_line_sent = true;
+        self.last_write = Some(Instant::now());
+        self.flush_buffer()
+    }
+
+    /// Dispatches a click event to the module that owns the clicked block,
+    /// the click actions, the `on_click` callback and the click handler.
+    pub fn handle_click(&mut self, event: &ClickEvent) {
+        self.modules.click(event);
+        let block = self.status_line.iter().find(|b| event.is_on(b));
+
+        for action in self.click_actions.iter().filter(|a| a.matches(event)) {
+            if let Err(e) = action.run(event, block) {
+                eprintln!("r3status: failed to run `{}`: {}", action.command, e);
+            }
+        }
+        if let Some(ref mut f) = self.on_click {
+            f(event);
+        }
+
+        let failed = match self.click_handler {
+            Some(ref mut handler) => handler.send(event).err(),
+            None => None,
+        };
+        if let Some(e) = failed {
+            eprintln!("r3status: click handler failed, disabling it: {}", e);
+            self.click_handler = None;
+        }
     }
 
-    pub fn write_msg(&mut self, msg: &str) -> io::Result<()> {
-        let m = Block { full_text: msg.to_string(), .. Default::default()};
-        self.buffer = json::encode(&vec![m]).unwrap();
+    /// Applies a reply from the click handler to the clicked block, and
+    /// returns whether that changed anything. Blocks without a name cannot be
+    /// told apart, so replies to clicks on them are ignored.
+    fn handle_click_reply(&mut self, reply: String) -> bool {
+        let (event, reply) = match self.click_handler.as_mut().and_then(|h| h.reply(reply)) {
+            Some(answer) => answer,
+            None => return false,
+        };
+        if event.name.is_none() {
+            return false;
+        }
 
-        try!(self.flush_buffer());
-        self.write_str(",")
+        let key = (event.name, event.instance);
+        if reply.is_empty() {
+            self.full_text_overrides.remove(&key).is_some()
+        } else {
+            self.full_text_overrides.insert(key, reply.clone()) != Some(reply)
+        }
+    }
+
+    /// Replaces the `full_text` of the blocks that the click handler has
+    /// answered with some text.
+    fn apply_overrides(&mut self) {
+        for block in &mut self.status_line {
+            let key = (block.name.clone(), block.instance.clone());
+            if let Some(text) = self.full_text_overrides.get(&key) {
+                block.full_text = text.clone();
+            }
+        }
     }
 
-    pub fn pipe_header(&mut self) -> io::Result<()> {
-        try!(self.read_line());
+    /// Writes `header` with the fields that r3status takes care of itself.
+    pub fn write_header(&mut self, header: Header) -> R3Result<()> {
+        let mut h = header;
+        self.header_config.apply(&mut h);
 
-        let mut h: Header = json::decode(&self.buffer).unwrap();
-        h.click_events = Some(true);
-        self.buffer = json::encode(&h).unwrap();
+        self.buffer = try!(json::encode(&h));
+        self.header_sent = true;
         self.flush_buffer()
     }
 
-    pub fn pipe_line(&mut self) -> io::Result<()> {
-        try!(self.read_line());
+    fn write_array_start(&mut self) -> R3Result<()> {
+        self.buffer = "[".to_string();
+        self.array_started = true;
         self.flush_buffer()
     }
 
-    pub fn run(&mut self) -> io::Result<()> {
-        let mut i3s = try!(spawn_i3status(self.config_file.as_ref()));
+    /// Makes sure that the header and the start of the array have been
+    /// written, using the default header if the first upstream has not sent
+    /// its own yet, so that i3bar is able to show a status line.
+    fn start_array(&mut self) -> R3Result<()> {
+        if !self.header_sent {
+            try!(self.write_header(Header::default()));
+        }
+        if !self.array_started {
+            try!(self.write_array_start());
+        }
+        Ok(())
+    }
+
+    /// Rebuilds `status_line` from the last lines of the upstreams and the
+    /// blocks of the modules, run through the thresholds and the filters,
+    /// and followed by the notifications. Hidden modules and blocks are left out.
+    /// Notifications that replace the line leave out everything else.
+    pub fn rebuild_status_line(&mut self) {
+        if self.notifications.config().replace_line() && !self.notifications.is_empty() {
+            self.status_line = self.notifications.blocks();
+            return;
+        }
+
+        let hidden = &self.hidden;
+        let upstream: Vec<Block> = self.upstream_lines.iter()
+            .flat_map(|line| line.iter().cloned())
+            .collect();
+        let modules: Vec<_> = self.modules.blocks().into_iter()
+            .filter(|&(name, _)| !hidden.contains(name))
+            .collect();
+
+        self.status_line = self.placements.merge(&upstream, &modules);
+        self.status_line.retain(|b| b.name.as_ref().map_or(true, |name| !hidden.contains(name)));
+        self.thresholds.apply(&mut self.status_line, Instant::now());
+        filter::apply_all(&self.filters, &mut self.status_line);
+        self.apply_overrides();
+        self.status_line.extend(self.notifications.blocks());
+    }
+
+    /// Writes `status_line`, unless it is the same as the last line that
+    /// was written and deduplication is on, or `force` is set.
+    pub fn write_status_line(&mut self, force: bool) -> R3Result<()> {
+        let line = try!(encode_status_line(&self.status_line, self.first_line_sent));
+        if !force && self.dedup && line.trim_start_matches(',') == self.last_line {
+            return Ok(());
+        }
+        self.write_status(line)
+    }
+
+    /// When the next status line may be written, going by `min_interval`.
+    fn next_write(&self) -> Option<Instant> {
+        self.last_write.map(|t| t + self.min_interval)
+    }
+
+    /// Pauses the upstreams while i3bar has the bar hidden, and has them
+    /// print a fresh status line as soon as the bar is shown again, or when
+    /// r3status gets `SIGUSR1`. The signals are also sent on to `events`, to
+    /// refresh the modules.
+    fn handle_signals(&self, events: Sender<Event>) -> io::Result<()> {
+        let children: Vec<_> = self.upstream_pids.iter().cloned()
+            .zip(self.upstreams.iter().map(|u| u.refresh_signal()))
+            .collect();
+        let paused = self.paused.clone();
+        let stop_signal = self.header_config.stop_signal();
+        let cont_signal = self.header_config.cont_signal();
+
+        let mut signals = vec![stop_signal, cont_signal, signal::REFRESH_SIGNAL];
+        for n in self.modules.signals() {
+            match signal::rt_signal(n) {
+                Some(sig) => signals.push(sig),
+                None => eprintln!("r3status: there is no signal SIGRTMIN+{}", n),
+            }
+        }
+
+        signal::spawn_watcher(&signals, move |sig| {
+            if sig == stop_signal || sig == cont_signal {
+                paused.store(sig == stop_signal, Ordering::SeqCst);
+            }
+
+            for &(ref pid, refresh_signal) in &children {
+                let pid = pid.load(Ordering::SeqCst);
+                if sig == stop_signal {
+                    signal::kill(pid, libc::SIGSTOP);
+                } else if sig == cont_signal || sig == signal::REFRESH_SIGNAL {
+                    if sig == cont_signal {
+                        signal::kill(pid, libc::SIGCONT);
+                    }
+                    if let Some(refresh_signal) = refresh_signal {
+                        signal::kill(pid, refresh_signal);
+                    }
+                }
+            }
+            let _ = events.send(Event::Signal(sig));
+        })
+    }
+
+    /// Runs until an upstream exits with restarting disabled, or until i3bar
+    /// goes away. In the first case, the exit status of the upstream is
+    /// returned in `R3Error::Eof`.
+    pub fn run(&mut self) -> R3Result<()> {
+        self.upstream_pids = self.upstreams.iter().map(|_| Arc::new(AtomicUsize::new(0))).collect();
+        self.upstream_lines = vec![Vec::new(); self.upstreams.len()];
+
+        let (tx, rx) = mpsc::channel();
+        let result = self.start(tx).and_then(|_| self.event_loop(rx));
+        result.map_err(|e| {
+            // An exited upstream has already had its say in the bar
+            match e {
+                R3Error::Eof(_) => (),
+                ref e => {
+                    let _ = self.write_error(e);
+                }
+            }
+            self.shutdown(e)
+        })
+    }
+
+    /// Starts the threads that send their events to `events`. In standalone
+    /// mode there are no upstreams, and the header is written right away.
+    fn start(&mut self, events: Sender<Event>) -> R3Result<()> {
+        // A missing config is not worth restarting i3status over
+        if let Some(ref config) = self.config_file {
+            if !self.standalone && self.upstreams.iter().any(|u| u.is_i3status()) {
+                try!(upstream::check_config_file(Path::new(config))
+                     .map_err(|e| R3Error::Child(e.to_string())));
+            }
+        }
+        try!(self.handle_signals(events.clone()));
+
+        if let Some(path) = self.control_socket.clone() {
+            match control::spawn_listener(&path, events.clone()) {
+                Ok(()) => self.listening_on = Some(path),
+                Err(e) => eprintln!("r3status: {}", e),
+            }
+        }
+
+        if self.standalone {
+            try!(self.write_header(Header::default()));
+            try!(self.write_array_start());
+        } else {
+            let shared = upstream::Shared {
+                config_file: self.config_file.clone(),
+                restart: self.restart,
+                utf8_policy: self.utf8_policy,
+                invalid_utf8: self.invalid_utf8.clone(),
+                paused: self.paused.clone(),
+                events: events.clone(),
+            };
+            for (i, upstream) in self.upstreams.iter().enumerate() {
+                upstream::spawn(i, upstream.clone(), self.upstream_pids[i].clone(), shared.clone());
+            }
+        }
+
+        // i3bar sends its click events on our stdin, unless they are disabled
+        if self.header_config.click_events() {
+            click::spawn_reader(io::stdin(), events.clone());
+            if let Some(ref cmd) = self.click_handler_cmd {
+                self.click_handler = Some(try!(ClickHandler::spawn(cmd, events)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the upstreams, clicks, signals and modules that are due, and
+    /// writes a new status line whenever one of them changed something. In
+    /// standalone mode, a line is also written every `interval`. Nothing is
+    /// written while the bar is hidden, like with a stopped i3status.
+    ///
+    /// Changes that come in quicker than `min_interval` are held back, and
+    /// written as one line once the interval is up.
+    fn event_loop(&mut self, events: Receiver<Event>) -> R3Result<()> {
+        let mut next_tick = if self.standalone { Some(Instant::now()) } else { None };
+        let mut pending = false;
+
+        loop {
+            let writable = self.array_started && !self.is_paused();
+            let modules_due = if self.is_paused() { None } else { self.modules.next_update() };
+            let write_due = if pending && writable { self.next_write() } else { None };
+            let notifications_due = self.notifications.next_update();
+            let deadline = [modules_due, next_tick, write_due, notifications_due].iter().filter_map(|&t| t).min();
+
+            let event = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    let timeout = if deadline > now { deadline - now } else { Duration::from_secs(0) };
+                    match events.recv_timeout(timeout) {
+                        Ok(event) => Some(event),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => return Err(R3Error::Eof(None)),
+                    }
+                }
+                None => Some(try!(events.recv().map_err(|_| R3Error::Eof(None)))),
+            };
+            let mut changed = match event {
+                Some(event) => try!(self.handle_event(event)),
+                None => false,
+            };
+
+            let now = Instant::now();
+            if next_tick.map_or(false, |tick| tick <= now) {
+                next_tick = Some(now + self.interval);
+                changed = true;
+            }
+            if !self.is_paused() && self.modules.update(now) {
+                changed = true;
+            }
+            if self.notifications.update(now) {
+                changed = true;
+            }
+            pending = pending || changed;
+
+            let writable = self.array_started && !self.is_paused();
+            if pending && writable && self.next_write().map_or(true, |t| t <= now) {
+                self.rebuild_status_line();
+                let force = mem::replace(&mut self.force_write, false);
+                try!(self.write_status_line(force));
+                pending = false;
+            }
+        }
+    }
+
+    /// Applies `event`, and returns whether the status line has to be rebuilt.
+    fn handle_event(&mut self, event: Event) -> R3Result<bool> {
+        match event {
+            // The header of the first upstream is the one that i3bar gets
+            Event::Header(i, header) => {
+                if i == 0 && !self.header_sent {
+                    try!(self.write_header(header));
+                    try!(self.write_array_start());
+                }
+                Ok(false)
+            }
+            Event::Line(i, blocks) => {
+                self.upstream_lines[i] = blocks;
+                Ok(true)
+            }
+            Event::Error(i, e) => {
+                try!(self.start_array());
+                let e = R3Error::Child(format!("{}: {}", self.upstreams[i].name(), e));
+                self.upstream_lines[i] = vec![error_block(&e)];
+                Ok(true)
+            }
+            Event::Message(i, msg) => {
+                try!(self.start_array());
+                self.upstream_lines[i] = vec![Block { full_text: msg, .. Default::default() }];
+                Ok(true)
+            }
+            Event::Exit(e) => Err(e),
+            // Clicked modules are updated right away, which rebuilds the line
+            Event::Click(event) => {
+                self.handle_click(&event);
+                Ok(false)
+            }
+            Event::ClickReply(reply) => Ok(self.handle_click_reply(reply)),
+            Event::Signal(sig) => Ok(self.handle_signal(sig)),
+            Event::Control(command, reply) => {
+                let (changed, answer) = self.handle_command(command);
+                let _ = reply.send(answer);
+                Ok(changed)
+            }
+        }
+    }
+
+    /// Carries out a command from the control socket, and returns whether
+    /// the status line has to be rebuilt, along with the reply.
+    fn handle_command(&mut self, command: Command) -> (bool, Json) {
+        let ok = || control::ok_reply(BTreeMap::new());
+
+        match command {
+            Command::Refresh => {
+                self.modules.refresh_all();
+                self.refresh_upstreams();
+                (true, ok())
+            }
+            Command::SetText { name, instance, text } => {
+                match text {
+                    Some(text) => self.full_text_overrides.insert((Some(name), instance), text),
+                    None => self.full_text_overrides.remove(&(Some(name), instance)),
+                };
+                (true, ok())
+            }
+            Command::Notify(notification) => {
+                self.notify(notification);
+                (true, ok())
+            }
+            Command::Hide(module) => (self.hidden.insert(module), ok()),
+            Command::Show(module) => (self.hidden.remove(&module), ok()),
+            Command::Reload => match self.reload_config() {
+                Ok(()) => (true, ok()),
+                Err(e) => (false, control::error_reply(&e.to_string())),
+            },
+            Command::Dump => {
+                let mut fields = BTreeMap::new();
+                fields.insert("status_line".to_string(), self.status_line.to_json());
+                (false, control::ok_reply(fields))
+            }
+        }
+    }
 
-        if let Some(i3out) = i3s.stdout {
-            self.reader = Some(BufReader::new(i3out));
+    /// Reads the r3status config file again. The settings that only matter
+    /// when r3status starts are left as they are.
+    fn reload_config(&mut self) -> R3Result<()> {
+        let path = match self.r3_config_file {
+            Some(ref path) => path.clone(),
+            None => return Err(R3Error::Io(io::Error::new(io::ErrorKind::NotFound,
+                                                          "r3status was started without a config file"))),
+        };
 
-            try!(self.pipe_header());
-            // Pipe the start of the infinate array
-            try!(self.pipe_line());
-            // Pipe the first line, this is the only line that is not prefixed with `,`
-            try!(self.pipe_line());
+        let mut config = try!(Config::load(&path));
+        config.upstreams = None;
+        config.standalone = None;
+        config.restart = None;
+        config.invalid_utf8 = None;
+        config.header = None;
+        config.click_handler = None;
+        config.control_socket = None;
+        self.apply_config(config);
+        Ok(())
+    }
 
-            loop {
-                try!(self.pipe_line());
+    /// Has the upstreams that have a refresh signal print a fresh line.
+    fn refresh_upstreams(&self) {
+        for (pid, upstream) in self.upstream_pids.iter().zip(&self.upstreams) {
+            if let Some(sig) = upstream.refresh_signal() {
+                signal::kill(pid.load(Ordering::SeqCst), sig);
             }
+        }
+    }
+
+    /// Refreshes the modules that `sig` is meant for, and returns whether the
+    /// status line has to be rebuilt right away.
+    fn handle_signal(&mut self, sig: c_int) -> bool {
+        if sig == signal::REFRESH_SIGNAL {
+            self.modules.refresh_all();
+            true
+        } else if let Some(n) = signal::rt_offset(sig) {
+            // The refreshed modules are updated right away, which rebuilds the line
+            self.modules.refresh_signal(n);
+            false
+        } else if sig == self.header_config.cont_signal() {
+            // A fresh line as soon as the bar is shown again
+            self.force_write = true;
+            true
         } else {
-            println!("Failed to aquire handle to i3status' `stdout`");
-            println!("Killling i3status...");
-            i3s.kill()
+            false
         }
     }
+
+    /// Stops the upstreams and the click handler, and removes the control socket.
+    fn shutdown(&mut self, cause: R3Error) -> R3Error {
+        self.click_handler = None;
+        if let Some(path) = self.listening_on.take() {
+            let _ = fs::remove_file(path);
+        }
+        for pid in &self.upstream_pids {
+            signal::kill(pid.load(Ordering::SeqCst), libc::SIGKILL);
+        }
+        cause
+    }
+}
+
+/// An urgent block that shows `e`.
+fn error_block(e: &R3Error) -> Block {
+    Block {
+        full_text: format!("r3status: {}", e),
+        color: Some(ERROR_COLOR.to_string()),
+        urgent: Some(true),
+        name: Some("r3status".to_string()),
+        .. Default::default()
+    }
 }
 
 pub fn run() {
     let mut r3 = R3Status::new();
 
     if let Err(e) = r3.run() {
-        println!("Failed to spawn r3status: {:?}", e);
+        eprintln!("r3status: {}", e);
+        process::exit(1)
     }
 }
 
-fn spawn_i3status<P: AsRef<Path>>(_config: Option<P>) -> io::Result<Child> {
-    Command::new("i3status")
-        .stdin(process::Stdio::null())
-        .stdout(process::Stdio::piped())
-        .stderr(process::Stdio::inherit())
-        .spawn()
+/// Decodes one line of the infinite array, which is the list of blocks,
+/// optionally prefixed with the `,` separating it from the previous line.
+/// Some upstreams put the `,` at the end of the line instead.
+fn parse_status_line(line: &str) -> Result<Vec<Block>, DecoderError> {
+    let line = line.trim();
+    let line = if line.starts_with(",") { &line[1..] } else { line };
+    let line = if line.ends_with(",") { &line[..line.len() - 1] } else { line };
+
+    let json = try!(Json::from_str(line).map_err(DecoderError::ParseError));
+    match json {
+        Json::Array(blocks) => blocks.iter().map(Block::from_json).collect(),
+        other => Err(DecoderError::ExpectedError("Array".to_string(), other.to_string())),
+    }
+}
+
+fn parse_header(line: &str) -> Result<Header, DecoderError> {
+    let json = try!(Json::from_str(line.trim()).map_err(DecoderError::ParseError));
+    Header::from_json(&json)
+}
+
+/// Encodes `blocks` as one line of the infinite array. Every line but the
+/// first one sent to i3bar has to be prefixed with `,`.
+fn encode_status_line(blocks: &[Block], continuation: bool)
+                      -> Result<String, json::EncoderError> {
+    let line = try!(json::encode(&blocks));
+    Ok(if continuation { format!(",{}", line) } else { line })
 }
 
 #[test]
@@ -207,3 +1150,129 @@ fn test_encode_decode_alignment() {
     assert_eq!(Ok(Alignment::Left), json::decode(r#""left""#));
     assert_eq!(Ok(Alignment::Center), json::decode(r#""center""#));
 }
+
+#[test]
+fn test_parse_status_line() {
+    let first = r#"[{"name":"disk_info","full_text":"42.0 GiB"}]"#;
+    let next = r##",[{"full_text":"W: down","color":"#FF0000"},{"full_text":"E: up"}]"##;
+
+    let blocks = parse_status_line(first).unwrap();
+    assert_eq!(1, blocks.len());
+    assert_eq!(Some("disk_info".to_string()), blocks[0].name);
+    assert_eq!("42.0 GiB", blocks[0].full_text);
+
+    let blocks = parse_status_line(next).unwrap();
+    assert_eq!(2, blocks.len());
+    assert_eq!(Some("#FF0000".to_string()), blocks[0].color);
+    assert_eq!("E: up", blocks[1].full_text);
+
+    let trailing = r#"[{"full_text":"E: up"}],"#;
+    assert_eq!(1, parse_status_line(trailing).unwrap().len());
+
+    assert!(parse_status_line("[").is_err());
+}
+
+#[test]
+fn test_encode_status_line() {
+    let blocks = vec![Block { full_text: "E: up".to_string(), .. Default::default() }];
+    assert_eq!(r#"[{"full_text":"E: up"}]"#, encode_status_line(&blocks, false).unwrap());
+    assert_eq!(r#",[{"full_text":"E: up"}]"#, encode_status_line(&blocks, true).unwrap());
+}
+
+#[test]
+fn test_encode_block_skips_unset_fields() {
+    let b = Block {
+        full_text: "E: up".to_string(),
+        align: Some(Alignment::Left),
+        urgent: Some(false),
+        .. Default::default()
+    };
+    assert_eq!(r#"{"align":"left","full_text":"E: up","urgent":false}"#,
+               json::encode(&b).unwrap());
+    assert_eq!(Ok(b.clone()), Block::from_json(&b.to_json()));
+}
+
+#[test]
+fn test_encode_decode_block_styling() {
+    let line = r##"[{"full_text":"CPU 12%","background":"#222222","border":"#FF0000","border_top":1,"border_right":0,"border_bottom":3,"border_left":0,"min_width":"CPU 100%","markup":"pango"},{"full_text":"E: up","min_width":120}]"##;
+
+    let blocks = parse_status_line(line).unwrap();
+    assert_eq!(Some("#222222".to_string()), blocks[0].background);
+    assert_eq!(Some("#FF0000".to_string()), blocks[0].border);
+    assert_eq!((Some(1), Some(0), Some(3), Some(0)),
+               (blocks[0].border_top, blocks[0].border_right,
+                blocks[0].border_bottom, blocks[0].border_left));
+    assert_eq!(Some(MinWidth::Text("CPU 100%".to_string())), blocks[0].min_width);
+    assert_eq!(Some("pango".to_string()), blocks[0].markup);
+    assert_eq!(Some(MinWidth::Pixels(120)), blocks[1].min_width);
+
+    let encoded = encode_status_line(&blocks, false).unwrap();
+    assert_eq!(blocks, parse_status_line(&encoded).unwrap());
+
+    assert!(parse_status_line(r#"[{"full_text":"E: up","min_width":true}]"#).is_err());
+    assert!(blocks[0].extra.is_empty());
+    assert!(parse_status_line(r#"[{"short_text":"E"}]"#).is_err());
+}
+
+#[test]
+fn test_block_keeps_unknown_fields() {
+    let line = r#"[{"full_text":"E: up","_sway_id":{"id":3,"tags":["a","b"]},"future_field":null,"name":"ethernet"}]"#;
+
+    let blocks = parse_status_line(line).unwrap();
+    assert_eq!(Some("ethernet".to_string()), blocks[0].name);
+    assert_eq!(vec!["_sway_id", "future_field"],
+               blocks[0].extra.keys().map(|k| &k[..]).collect::<Vec<_>>());
+    assert_eq!(Json::Null, blocks[0].extra["future_field"]);
+
+    assert_eq!(r#"[{"_sway_id":{"id":3,"tags":["a","b"]},"full_text":"E: up","future_field":null,"name":"ethernet"}]"#,
+               encode_status_line(&blocks, false).unwrap());
+}
+
+#[test]
+fn test_parse_header() {
+    let header = parse_header(r#"{"version":1,"click_events":false,"x_sway":true}"#).unwrap();
+    assert_eq!(1, header.version);
+    assert_eq!(Some(false), header.click_events);
+    assert_eq!(None, header.stop_signal);
+    assert_eq!(Some(&Json::Boolean(true)), header.extra.get("x_sway"));
+
+    assert!(parse_header(r#"{"click_events":true}"#).is_err());
+}
+
+#[test]
+fn test_header_config() {
+    let mut header = parse_header(r#"{"version":2,"x_sway":true}"#).unwrap();
+    HeaderConfig::default().apply(&mut header);
+    assert_eq!(format!(r#"{{"click_events":true,"cont_signal":{},"stop_signal":{},"version":1,"x_sway":true}}"#,
+                       libc::SIGCONT, libc::SIGUSR2),
+               json::encode(&header).unwrap());
+
+    let config = HeaderConfig {
+        click_events: Some(false),
+        stop_signal: Some(libc::SIGTSTP as usize),
+        cont_signal: None,
+    };
+    config.apply(&mut header);
+    assert_eq!(Some(false), header.click_events);
+    assert_eq!(Some(libc::SIGTSTP as usize), header.stop_signal);
+    assert_eq!(Some(libc::SIGCONT as usize), header.cont_signal);
+}
+
+#[test]
+fn test_click_reply_needs_a_name() {
+    let (tx, rx) = mpsc::channel();
+    let mut r3 = R3Status::new();
+    r3.click_handler = Some(ClickHandler::spawn("while read line; do echo clicked; done", tx).unwrap());
+
+    let unnamed = Block { full_text: "a".to_string(), .. Default::default() };
+    r3.status_line = vec![unnamed.clone(), Block { full_text: "b".to_string(), .. unnamed }];
+    r3.handle_click(&click::parse_event(r#"{"button":1,"x":0,"y":0}"#).unwrap().unwrap());
+    let reply = match rx.recv().unwrap() {
+        Event::ClickReply(reply) => reply,
+        other => panic!("expected a reply, got {:?}", other),
+    };
+    assert!(!r3.handle_click_reply(reply));
+
+    r3.apply_overrides();
+    assert_eq!(vec!["a", "b"], r3.status_line.iter().map(|b| &b.full_text[..]).collect::<Vec<_>>());
+}
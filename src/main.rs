@@ -1,5 +1,140 @@
 extern crate r3status;
 
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use r3status::{R3Error, R3Status, Upstream};
+
+const USAGE: &'static str = "Usage: r3status [-c <i3status config>] [-C <r3status config>] [--no-restart] [--standalone]
+                [-- <command> [<args>...]]
+       r3status msg [-s <socket>] <command> [<key>=<value>...]
+
+Wraps i3status, or any other <command> that speaks the i3bar protocol.
+
+Options:
+    -c, --config <file>     Path to the config file passed on to i3status,
+                            for other commands it goes in <args>
+    -C, --r3-config <file>  Path to the r3status config file
+    --no-restart            Exit with the exit code of the command when it
+                            exits, instead of restarting it
+    --standalone            Run without i3status, showing only the blocks of
+                            the built-in modules
+    -h, --help              Print this message
+
+Commands sent with `msg` go to the control socket of a running r3status:
+    refresh, set_text name=<name> [instance=<instance>] [text=<text>],
+    notify text=<text> [color=<color>] [urgent=true] [duration=<seconds>],
+    hide module=<name>, show module=<name>, reload and dump
+
+    -s, --socket <path>     Path to the control socket, by default
+                            $XDG_RUNTIME_DIR/r3status.sock";
+
 fn main() {
-    r3status::run();
+    let mut r3 = R3Status::new();
+    let mut args = env::args().skip(1).peekable();
+
+    if args.peek().map_or(false, |arg| arg == "msg") {
+        args.next();
+        msg(args.collect());
+    }
+
+    // The r3status config is loaded once all arguments are parsed, so that
+    // the flags override it no matter where they are
+    let mut config_file = false;
+    let mut r3_config = None;
+    let mut no_restart = false;
+    let mut standalone = false;
+    let mut upstream = None;
+
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-c" | "--config" => match args.next() {
+                Some(config) => {
+                    r3.config_file(&config);
+                    config_file = true;
+                }
+                None => usage_error("missing argument to `--config`"),
+            },
+            "-C" | "--r3-config" => match args.next() {
+                Some(config) => r3_config = Some(config),
+                None => usage_error("missing argument to `--r3-config`"),
+            },
+            "--no-restart" => no_restart = true,
+            "--standalone" => standalone = true,
+            "--" => match args.next() {
+                Some(command) => upstream = Some(Upstream::new(&command, args.by_ref().collect())),
+                None => usage_error("missing command after `--`"),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            other => usage_error(&format!("unknown argument `{}`", other)),
+        }
+    }
+
+    if let Some(config) = r3_config {
+        if let Err(e) = r3.load_config(&config) {
+            eprintln!("r3status: {}", e);
+            process::exit(1)
+        }
+    }
+    if no_restart {
+        r3.restart(false);
+    }
+    if standalone {
+        r3.standalone(true);
+    }
+    if let Some(upstream) = upstream {
+        if config_file && !upstream.is_i3status() {
+            usage_error("`--config` is only passed on to i3status, pass it in <args> instead");
+        }
+        r3.upstream(upstream);
+    }
+
+    match r3.run() {
+        Ok(()) => (),
+        Err(R3Error::Eof(status)) => {
+            eprintln!("r3status: {}", R3Error::Eof(status));
+            process::exit(status.and_then(|s| s.code()).unwrap_or(1))
+        }
+        Err(e) => {
+            eprintln!("r3status: {}", e);
+            process::exit(1)
+        }
+    }
+}
+
+/// Sends a command to a running r3status, and prints its reply.
+fn msg(args: Vec<String>) -> ! {
+    let (socket, words) = match args.first().map(|arg| &arg[..]) {
+        Some("-s") | Some("--socket") if args.len() > 1 => (Some(PathBuf::from(&args[1])), &args[2..]),
+        Some("-s") | Some("--socket") => usage_error("missing argument to `--socket`"),
+        _ => (r3status::default_socket_path(), &args[..]),
+    };
+    let socket = match socket {
+        Some(socket) => socket,
+        None => usage_error("`$XDG_RUNTIME_DIR` is not set, pass the path with `--socket`"),
+    };
+    let command = match r3status::encode_command(words) {
+        Ok(command) => command,
+        Err(e) => usage_error(&e),
+    };
+
+    match r3status::send_command(&socket, &command) {
+        Ok(reply) => {
+            println!("{}", reply);
+            process::exit(0)
+        }
+        Err(e) => {
+            eprintln!("r3status: {}", e);
+            process::exit(1)
+        }
+    }
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("r3status: {}\n\n{}", msg, USAGE);
+    process::exit(2)
 }
@@ -0,0 +1,188 @@
+use std::time::{Duration, Instant};
+
+use {Block, ClickEvent};
+
+/// A source of blocks that is built into r3status, rather than coming from
+/// i3status, and that is updated on its own schedule.
+pub trait Module: Send {
+    /// Identifies the module, and is used as the `name` of the blocks it
+    /// produces that do not have one, so that clicks find their way back.
+    fn name(&self) -> &str;
+
+    /// How long to wait between updates.
+    fn interval(&self) -> Duration;
+
+    fn update(&mut self) -> Vec<Block>;
+
+    /// Called for clicks on the blocks of the module, which is updated right
+    /// after, so that the click can have a visible effect.
+    fn on_click(&mut self, _event: &ClickEvent) {}
+
+    /// Binds the module to `SIGRTMIN+n`, which updates it right away, like
+    /// the `signal` of i3blocks.
+    fn signal(&self) -> Option<usize> {
+        None
+    }
+}
+
+struct Entry {
+    module: Box<dyn Module>,
+    blocks: Vec<Block>,
+    next_update: Instant,
+}
+
+/// The modules registered with r3status, in the order they were added.
+#[derive(Default)]
+pub struct Modules {
+    entries: Vec<Entry>,
+}
+
+impl Modules {
+    pub fn add(&mut self, module: Box<dyn Module>) {
+        self.entries.push(Entry {
+            module: module,
+            blocks: Vec::new(),
+            next_update: Instant::now(),
+        });
+    }
+
+    /// Updates the modules that are due at `now`, and returns whether any of
+    /// them were.
+    pub fn update(&mut self, now: Instant) -> bool {
+        let mut updated = false;
+
+        for entry in self.entries.iter_mut().filter(|e| e.next_update <= now) {
+            let mut blocks = entry.module.update();
+            for block in &mut blocks {
+                if block.name.is_none() {
+                    block.name = Some(entry.module.name().to_string());
+                }
+            }
+
+            entry.blocks = blocks;
+            entry.next_update = now + entry.module.interval();
+            updated = true;
+        }
+        updated
+    }
+
+    /// Makes every module due for an update.
+    pub fn refresh_all(&mut self) {
+        let now = Instant::now();
+        for entry in &mut self.entries {
+            entry.next_update = now;
+        }
+    }
+
+    /// Makes the modules that are bound to `SIGRTMIN+n` due for an update,
+    /// and returns whether there were any.
+    pub fn refresh_signal(&mut self, n: usize) -> bool {
+        let now = Instant::now();
+        let mut found = false;
+
+        for entry in self.entries.iter_mut().filter(|e| e.module.signal() == Some(n)) {
+            entry.next_update = now;
+            found = true;
+        }
+        found
+    }
+
+    /// The `n` of every `SIGRTMIN+n` that a module is bound to.
+    pub fn signals(&self) -> Vec<usize> {
+        let mut signals: Vec<usize> = self.entries.iter().filter_map(|e| e.module.signal()).collect();
+        signals.sort();
+        signals.dedup();
+        signals
+    }
+
+    /// When the next module is due for an update, if there are any modules.
+    pub fn next_update(&self) -> Option<Instant> {
+        self.entries.iter().map(|e| e.next_update).min()
+    }
+
+    /// The blocks of every module from their last update, along with the
+    /// name of the module.
+    pub fn blocks(&self) -> Vec<(&str, &[Block])> {
+        self.entries.iter().map(|e| (e.module.name(), &e.blocks[..])).collect()
+    }
+
+    /// Passes `event` on to the module that owns the clicked block, if any,
+    /// and returns whether there was one. Blocks from elsewhere that share the
+    /// name of a module are not the module's business.
+    pub fn click(&mut self, event: &ClickEvent) -> bool {
+        for entry in &mut self.entries {
+            if entry.blocks.iter().any(|b| event.is_on(b)) {
+                entry.module.on_click(event);
+                entry.next_update = Instant::now();
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+struct Counter {
+    count: usize,
+}
+
+#[cfg(test)]
+impl Module for Counter {
+    fn name(&self) -> &str {
+        "counter"
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(10)
+    }
+
+    fn update(&mut self) -> Vec<Block> {
+        self.count += 1;
+        vec![Block { full_text: self.count.to_string(), .. Default::default() }]
+    }
+
+    fn on_click(&mut self, event: &ClickEvent) {
+        self.count += event.button * 100;
+    }
+
+    fn signal(&self) -> Option<usize> {
+        Some(2)
+    }
+}
+
+#[test]
+fn test_modules() {
+    let mut modules = Modules::default();
+    modules.add(Box::new(Counter { count: 0 }));
+    let now = Instant::now();
+    assert!(modules.update(now));
+    assert!(!modules.update(now + Duration::from_secs(5)));
+    assert_eq!(Some(now + Duration::from_secs(10)), modules.next_update());
+
+    let blocks = modules.blocks();
+    assert_eq!("counter", blocks[0].0);
+    assert_eq!("1", blocks[0].1[0].full_text);
+    assert_eq!(Some("counter".to_string()), blocks[0].1[0].name);
+
+    assert!(modules.update(now + Duration::from_secs(10)));
+    assert_eq!("2", modules.blocks()[0].1[0].full_text);
+
+    let event = ::click::parse_event(r#"{"name":"counter","button":1,"x":0,"y":0}"#)
+        .unwrap().unwrap();
+    assert!(modules.click(&event));
+    assert!(modules.update(Instant::now()));
+    assert_eq!("103", modules.blocks()[0].1[0].full_text);
+
+    let event = ::click::parse_event(r#"{"name":"volume","button":1,"x":0,"y":0}"#)
+        .unwrap().unwrap();
+    assert!(!modules.click(&event));
+    let event = ::click::parse_event(r#"{"name":"counter","instance":"i3status","button":1,"x":0,"y":0}"#)
+        .unwrap().unwrap();
+    assert!(!modules.click(&event));
+
+    assert_eq!(vec![2], modules.signals());
+    assert!(!modules.refresh_signal(1));
+    assert!(modules.refresh_signal(2));
+    assert!(modules.update(Instant::now()));
+    assert_eq!("104", modules.blocks()[0].1[0].full_text);
+}
@@ -0,0 +1,220 @@
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use Block;
+
+/// How long a notification is shown for, unless it says otherwise.
+const DEFAULT_DURATION_SECS: u64 = 5;
+/// How long each notification is shown for at a time, when rotating.
+const DEFAULT_ROTATE_INTERVAL_SECS: u64 = 2;
+
+/// A message that is shown in the bar for a while.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct Notification {
+    pub text: String,
+    pub color: Option<String>,
+    pub urgent: Option<bool>,
+    /// The number of seconds to show it for.
+    pub duration: Option<u64>,
+}
+
+impl Notification {
+    pub fn new(text: &str) -> Notification {
+        Notification { text: text.to_string(), color: None, urgent: None, duration: None }
+    }
+
+    fn block(&self) -> Block {
+        Block {
+            full_text: self.text.clone(),
+            color: self.color.clone(),
+            urgent: self.urgent,
+            name: Some("notification".to_string()),
+            .. Default::default()
+        }
+    }
+}
+
+/// How notifications are shown.
+#[derive(Clone, Debug, Default, PartialEq, RustcDecodable)]
+pub struct NotificationConfig {
+    /// Set to `true` to show the notifications in place of the whole status
+    /// line, instead of after it.
+    pub replace_line: Option<bool>,
+    /// Set to `true` to show one notification at a time, taking turns every
+    /// `rotate_interval` seconds, instead of all of them side by side.
+    pub rotate: Option<bool>,
+    pub rotate_interval: Option<u64>,
+}
+
+impl NotificationConfig {
+    pub fn replace_line(&self) -> bool {
+        self.replace_line.unwrap_or(false)
+    }
+
+    fn rotate_interval(&self) -> Duration {
+        Duration::from_secs(self.rotate_interval.unwrap_or(DEFAULT_ROTATE_INTERVAL_SECS))
+    }
+}
+
+/// The notifications that have not expired yet, in the order they were
+/// pushed, along with when they expire.
+#[derive(Default)]
+pub struct Notifications {
+    config: NotificationConfig,
+    queue: Vec<(Notification, Instant)>,
+    /// The notification that is shown when rotating, and since when.
+    current: usize,
+    turn_started: Option<Instant>,
+}
+
+impl Notifications {
+    pub fn configure(&mut self, config: NotificationConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> &NotificationConfig {
+        &self.config
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn push(&mut self, notification: Notification, now: Instant) {
+        let duration = notification.duration.unwrap_or(DEFAULT_DURATION_SECS);
+        self.queue.push((notification, now + Duration::from_secs(duration)));
+        if self.turn_started.is_none() {
+            self.turn_started = Some(now);
+        }
+    }
+
+    /// Removes the notifications that have expired, and moves on to the next
+    /// one when rotating. Returns whether that changed what is shown.
+    pub fn update(&mut self, now: Instant) -> bool {
+        let before = self.blocks();
+
+        // The shown notification keeps its turn when others expire, and the
+        // next one gets a full turn when it expires itself
+        let current_expired = self.queue.get(self.current).map_or(false, |&(_, until)| until <= now);
+        self.current -= self.queue.iter().take(self.current).filter(|&&(_, until)| until <= now).count();
+
+        self.queue.retain(|&(_, until)| until > now);
+        if self.queue.is_empty() {
+            self.current = 0;
+            self.turn_started = None;
+        } else if current_expired {
+            self.turn_started = Some(now);
+        } else if self.rotating() && self.next_turn().map_or(false, |turn| turn <= now) {
+            self.current += 1;
+            self.turn_started = Some(now);
+        }
+        if !self.queue.is_empty() {
+            self.current %= self.queue.len();
+        }
+
+        self.blocks() != before
+    }
+
+    /// When `update` has something to do next.
+    pub fn next_update(&self) -> Option<Instant> {
+        let expiry = self.queue.iter().map(|&(_, until)| until).min();
+        match (expiry, self.next_turn()) {
+            (Some(expiry), Some(turn)) if self.rotating() => Some(cmp::min(expiry, turn)),
+            _ => expiry,
+        }
+    }
+
+    /// The blocks of the notifications that are shown, which is only one of
+    /// them when rotating.
+    pub fn blocks(&self) -> Vec<Block> {
+        if self.config.rotate.unwrap_or(false) {
+            self.queue.get(self.current).map(|&(ref n, _)| n.block()).into_iter().collect()
+        } else {
+            self.queue.iter().map(|&(ref n, _)| n.block()).collect()
+        }
+    }
+
+    fn rotating(&self) -> bool {
+        self.config.rotate.unwrap_or(false) && self.queue.len() > 1
+    }
+
+    fn next_turn(&self) -> Option<Instant> {
+        self.turn_started.map(|started| started + self.config.rotate_interval())
+    }
+}
+
+#[test]
+fn test_notifications() {
+    use rustc_serialize::json;
+
+    let mut notifications = Notifications::default();
+    let now = Instant::now();
+    notifications.push(json::decode(r##"{ "text": "build failed", "color": "#FF0000" }"##).unwrap(),
+                       now);
+    notifications.push(json::decode(r#"{ "text": "tests passed", "duration": 1 }"#).unwrap(), now);
+
+    let blocks = notifications.blocks();
+    assert_eq!(2, blocks.len());
+    assert_eq!(Some("#FF0000".to_string()), blocks[0].color);
+    assert_eq!(Some(now + Duration::from_secs(1)), notifications.next_update());
+
+    assert!(!notifications.update(now));
+    assert!(notifications.update(now + Duration::from_secs(1)));
+    assert_eq!("build failed", notifications.blocks()[0].full_text);
+    assert!(notifications.update(now + Duration::from_secs(5)));
+    assert!(notifications.is_empty());
+    assert_eq!(None, notifications.next_update());
+}
+
+#[test]
+fn test_rotate_notifications() {
+    let mut notifications = Notifications::default();
+    notifications.configure(NotificationConfig { rotate: Some(true), .. Default::default() });
+    let now = Instant::now();
+    let at = |secs| now + Duration::from_secs(secs);
+    let text = |n: &Notifications| n.blocks().iter().map(|b| b.full_text.clone()).collect::<Vec<_>>();
+
+    notifications.push(Notification::new("a"), now);
+    notifications.push(Notification::new("b"), now);
+    notifications.push(Notification { duration: Some(3), .. Notification::new("c") }, now);
+    assert_eq!(vec!["a"], text(&notifications));
+    assert_eq!(Some(at(2)), notifications.next_update());
+
+    assert!(notifications.update(at(2)));
+    assert_eq!(vec!["b"], text(&notifications));
+    // `c` expires before it gets its turn
+    assert!(!notifications.update(at(3)));
+    assert!(notifications.update(at(4)));
+    assert_eq!(vec!["a"], text(&notifications));
+    assert!(notifications.update(at(5)));
+    assert!(notifications.is_empty());
+}
+
+#[test]
+fn test_rotate_expiring_notifications() {
+    let mut notifications = Notifications::default();
+    notifications.configure(NotificationConfig { rotate: Some(true), .. Default::default() });
+    let now = Instant::now();
+    let at = |secs| now + Duration::from_secs(secs);
+    let text = |n: &Notifications| n.blocks().iter().map(|b| b.full_text.clone()).collect::<Vec<_>>();
+
+    for &(text, duration) in &[("a", 5), ("b", 9), ("c", 12), ("d", 20)] {
+        notifications.push(Notification { duration: Some(duration), .. Notification::new(text) }, now);
+    }
+    assert!(notifications.update(at(2)));
+    assert!(notifications.update(at(4)));
+    assert_eq!(vec!["c"], text(&notifications));
+
+    // `a` expires while `c` is shown, which keeps its turn
+    assert!(!notifications.update(at(5)));
+    assert_eq!(vec!["c"], text(&notifications));
+    assert!(notifications.update(at(6)));
+    assert_eq!(vec!["d"], text(&notifications));
+    assert!(notifications.update(at(8)));
+    assert_eq!(vec!["b"], text(&notifications));
+
+    // `b` expires while it is shown, and `c` gets a full turn
+    assert!(notifications.update(at(9)));
+    assert_eq!(vec!["c"], text(&notifications));
+    assert_eq!(Some(at(11)), notifications.next_update());
+}
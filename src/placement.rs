@@ -0,0 +1,247 @@
+use rustc_serialize::{Decodable, Decoder};
+
+use Block;
+
+/// A block from i3status that module blocks are placed relative to. Without
+/// an `instance`, the first block with the name is used.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anchor {
+    pub name: String,
+    pub instance: Option<String>,
+}
+
+impl Anchor {
+    fn find(&self, blocks: &[Block]) -> Option<usize> {
+        blocks.iter().position(|b| {
+            b.name.as_ref() == Some(&self.name)
+                && (self.instance.is_none() || b.instance == self.instance)
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Position {
+    Before(Anchor),
+    After(Anchor),
+    /// In place of the anchor, which is left out of the status line.
+    Replace(Anchor),
+    /// At an index among the blocks from i3status.
+    Index(usize),
+}
+
+/// Where the blocks of a module go in the status line, decoded from an
+/// object like `{ "module": "ci", "before": "wireless", "instance": "wlan0" }`,
+/// with exactly one of `before`, `after`, `replace` and `index`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Placement {
+    pub module: String,
+    pub position: Position,
+}
+
+impl Decodable for Placement {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Placement, D::Error> {
+        d.read_struct("Placement", 6, |d| {
+            let module: String = try!(d.read_struct_field("module", 0, Decodable::decode));
+            let before: Option<String> = try!(d.read_struct_field("before", 1, Decodable::decode));
+            let after: Option<String> = try!(d.read_struct_field("after", 2, Decodable::decode));
+            let replace: Option<String> = try!(d.read_struct_field("replace", 3, Decodable::decode));
+            let index: Option<usize> = try!(d.read_struct_field("index", 4, Decodable::decode));
+            let instance: Option<String> = try!(d.read_struct_field("instance", 5, Decodable::decode));
+
+            let anchor = |name| Anchor { name: name, instance: instance.clone() };
+            let position = match (before, after, replace, index) {
+                (Some(name), None, None, None) => Position::Before(anchor(name)),
+                (None, Some(name), None, None) => Position::After(anchor(name)),
+                (None, None, Some(name), None) => Position::Replace(anchor(name)),
+                (None, None, None, Some(index)) => Position::Index(index),
+                _ => return Err(d.error(&format!(
+                    "the placement of `{}` needs exactly one of `before`, `after`, `replace` \
+                     and `index`", module))),
+            };
+            Ok(Placement { module: module, position: position })
+        })
+    }
+}
+
+/// The named blocks around the spot where the blocks of a module went.
+struct Neighbours {
+    /// The blocks after the spot, nearest first.
+    after: Vec<Anchor>,
+    /// The blocks before the spot, nearest first.
+    before: Vec<Anchor>,
+}
+
+impl Neighbours {
+    fn new(i3status: &[Block], slot: usize) -> Neighbours {
+        Neighbours {
+            after: anchors(i3status[slot..].iter()),
+            before: anchors(i3status[..slot].iter().rev()),
+        }
+    }
+
+    /// The spot next to the nearest of the blocks that is still around.
+    fn find(&self, i3status: &[Block]) -> Option<usize> {
+        self.after.iter().filter_map(|a| a.find(i3status)).next()
+            .or_else(|| self.before.iter().filter_map(|a| a.find(i3status)).next().map(|i| i + 1))
+    }
+}
+
+fn anchors<'a, I: Iterator<Item = &'a Block>>(blocks: I) -> Vec<Anchor> {
+    blocks.filter_map(|b| b.name.as_ref().map(|name| Anchor {
+        name: name.clone(),
+        instance: b.instance.clone(),
+    })).collect()
+}
+
+struct Rule {
+    placement: Placement,
+    /// The blocks around where the module went the last time its anchor was
+    /// there. The module stays next to them while the anchor is missing, like
+    /// when a wireless interface goes away.
+    last_neighbours: Option<Neighbours>,
+}
+
+/// Merges the blocks of the modules into the ones from i3status. Modules
+/// without a placement go after the blocks from i3status.
+#[derive(Default)]
+pub struct Placements {
+    rules: Vec<Rule>,
+}
+
+impl Placements {
+    pub fn add(&mut self, placement: Placement) {
+        self.rules.retain(|r| r.placement.module != placement.module);
+        self.rules.push(Rule { placement: placement, last_neighbours: None });
+    }
+
+    pub fn clear(&mut self) {
+        self.rules.clear();
+    }
+
+    /// Places the blocks of every module, given in the order that the modules
+    /// were added, among the blocks from i3status. Modules that end up in the
+    /// same spot keep that order.
+    pub fn merge(&mut self, i3status: &[Block], modules: &[(&str, &[Block])]) -> Vec<Block> {
+        let len = i3status.len();
+        let mut replaced = vec![false; len];
+        // The index of the block from i3status that each module goes before
+        let mut slots = Vec::with_capacity(modules.len());
+
+        for &(module, _) in modules {
+            let rule = match self.rules.iter_mut().find(|r| r.placement.module == module) {
+                Some(rule) => rule,
+                None => {
+                    slots.push(len);
+                    continue;
+                }
+            };
+
+            let slot = match rule.placement.position {
+                Position::Before(ref anchor) => anchor.find(i3status),
+                Position::After(ref anchor) => anchor.find(i3status).map(|i| i + 1),
+                Position::Replace(ref anchor) => anchor.find(i3status).map(|i| {
+                    replaced[i] = true;
+                    i
+                }),
+                Position::Index(index) => Some(index),
+            };
+            let slot = match slot {
+                Some(slot) => {
+                    let slot = slot.min(len);
+                    rule.last_neighbours = Some(Neighbours::new(i3status, slot));
+                    slot
+                }
+                None => rule.last_neighbours.as_ref().and_then(|n| n.find(i3status)).unwrap_or(len),
+            };
+            slots.push(slot);
+        }
+
+        let mut line = Vec::with_capacity(len + modules.iter().map(|m| m.1.len()).sum::<usize>());
+        for i in 0..len + 1 {
+            for (&(_, blocks), &slot) in modules.iter().zip(&slots) {
+                if slot == i {
+                    line.extend(blocks.iter().cloned());
+                }
+            }
+            if i < len && !replaced[i] {
+                line.push(i3status[i].clone());
+            }
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+fn block(name: &str, instance: Option<&str>) -> Block {
+    Block {
+        full_text: name.to_string(),
+        name: Some(name.to_string()),
+        instance: instance.map(|i| i.to_string()),
+        .. Default::default()
+    }
+}
+
+#[cfg(test)]
+fn names(blocks: &[Block]) -> Vec<&str> {
+    blocks.iter().map(|b| &b.full_text[..]).collect()
+}
+
+#[test]
+fn test_decode_placement() {
+    use rustc_serialize::json;
+
+    let p: Placement = json::decode(r#"{"module":"ci","before":"wireless","instance":"wlan0"}"#)
+        .unwrap();
+    assert_eq!(Position::Before(Anchor {
+        name: "wireless".to_string(),
+        instance: Some("wlan0".to_string()),
+    }), p.position);
+
+    let p: Placement = json::decode(r#"{"module":"ci","index":0}"#).unwrap();
+    assert_eq!(Position::Index(0), p.position);
+
+    assert!(json::decode::<Placement>(r#"{"module":"ci"}"#).is_err());
+    assert!(json::decode::<Placement>(r#"{"module":"ci","before":"a","after":"b"}"#).is_err());
+}
+
+#[test]
+fn test_merge() {
+    let anchor = |name: &str| Anchor { name: name.to_string(), instance: None };
+    let mut placements = Placements::default();
+    placements.add(Placement {
+        module: "ci".to_string(),
+        position: Position::After(Anchor {
+            name: "wireless".to_string(),
+            instance: Some("wlan0".to_string()),
+        }),
+    });
+    placements.add(Placement { module: "vpn".to_string(), position: Position::Replace(anchor("ethernet")) });
+    placements.add(Placement { module: "clock".to_string(), position: Position::Index(0) });
+
+    let i3status = vec![block("wireless", Some("wlan0")), block("ethernet", Some("eth0")),
+                        block("load", None)];
+    let ci = vec![block("ci", None)];
+    let vpn = vec![block("vpn", None)];
+    let clock = vec![block("clock", None)];
+    let weather = vec![block("weather", None)];
+    let modules: Vec<(&str, &[Block])> = vec![("clock", &clock), ("weather", &weather),
+                                              ("ci", &ci), ("vpn", &vpn)];
+
+    assert_eq!(vec!["clock", "wireless", "ci", "vpn", "load", "weather"],
+               names(&placements.merge(&i3status, &modules)));
+
+    // The blocks stay next to the blocks they were next to while their
+    // anchors are missing
+    let i3status = vec![block("ethernet", Some("eth0")), block("load", None)];
+    assert_eq!(vec!["clock", "ci", "vpn", "load", "weather"],
+               names(&placements.merge(&i3status, &modules)));
+
+    let i3status = vec![block("load", None)];
+    assert_eq!(vec!["clock", "ci", "vpn", "load", "weather"],
+               names(&placements.merge(&i3status, &modules)));
+
+    let i3status = vec![block("ethernet", Some("eth0")), block("wireless", Some("wlan0")),
+                        block("load", None)];
+    assert_eq!(vec!["clock", "vpn", "wireless", "ci", "load", "weather"],
+               names(&placements.merge(&i3status, &modules)));
+}
@@ -0,0 +1,83 @@
+use libc::{self, c_int};
+
+use std::io;
+use std::mem;
+use std::ptr;
+use std::thread;
+
+/// The signal we ask i3bar to send when the bar is hidden. i3bar defaults to
+/// `SIGSTOP`, which cannot be caught, so we would never get to pause i3status.
+pub const STOP_SIGNAL: c_int = libc::SIGUSR2;
+/// The signal we ask i3bar to send when the bar is shown again.
+pub const CONT_SIGNAL: c_int = libc::SIGCONT;
+/// Refreshes everything, and is passed on to the upstreams. The same signal
+/// that i3status refreshes on.
+pub const REFRESH_SIGNAL: c_int = libc::SIGUSR1;
+
+/// `SIGRTMIN+n`, the signal that refreshes the modules bound to `n`, if
+/// there is such a realtime signal.
+pub fn rt_signal(n: usize) -> Option<c_int> {
+    let sig = libc::SIGRTMIN() as usize + n;
+    if sig <= libc::SIGRTMAX() as usize {
+        Some(sig as c_int)
+    } else {
+        None
+    }
+}
+
+/// The `n` of a `SIGRTMIN+n` signal.
+pub fn rt_offset(sig: c_int) -> Option<usize> {
+    if sig >= libc::SIGRTMIN() && sig <= libc::SIGRTMAX() {
+        Some((sig - libc::SIGRTMIN()) as usize)
+    } else {
+        None
+    }
+}
+
+/// Blocks `signals` and spawns a thread that waits for them with `sigwait`,
+/// calling `handler` with every signal that arrives.
+///
+/// The signal mask is inherited by threads spawned afterwards, so this has to
+/// be called before any other thread is started, or they might end up with
+/// the default disposition of the signal.
+pub fn spawn_watcher<F>(signals: &[c_int], mut handler: F) -> io::Result<()>
+    where F: FnMut(c_int) + Send + 'static
+{
+    let mut set: libc::sigset_t = unsafe { mem::zeroed() };
+    unsafe {
+        libc::sigemptyset(&mut set);
+        for &sig in signals {
+            libc::sigaddset(&mut set, sig);
+        }
+        let ret = libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut());
+        if ret != 0 {
+            return Err(io::Error::from_raw_os_error(ret));
+        }
+    }
+
+    thread::spawn(move || {
+        let mut sig = 0;
+        loop {
+            if unsafe { libc::sigwait(&set, &mut sig) } == 0 {
+                handler(sig);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Sends `sig` to the process `pid`, where a `pid` of 0 means that there is
+/// no process to signal.
+pub fn kill(pid: usize, sig: c_int) {
+    if pid != 0 {
+        unsafe { libc::kill(pid as libc::pid_t, sig); }
+    }
+}
+
+#[test]
+fn test_rt_signal() {
+    assert_eq!(Some(libc::SIGRTMIN() + 3), rt_signal(3));
+    assert_eq!(Some(3), rt_offset(libc::SIGRTMIN() + 3));
+    assert_eq!(None, rt_signal(1000));
+    assert_eq!(None, rt_offset(libc::SIGUSR1));
+}
@@ -0,0 +1,204 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use Block;
+
+/// The color and urgency of a block whose number is above `above`.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct Level {
+    pub above: f64,
+    pub color: Option<String>,
+    pub urgent: Option<bool>,
+}
+
+/// Sets the `color` and `urgent` of the blocks named `name` from the first
+/// number in their `full_text`, like
+/// `{ "name": "cpu_usage", "levels": [{ "above": 90, "color": "#FF0000", "urgent": true },
+/// { "above": 70, "color": "#FFFF00" }] }`.
+///
+/// A level is only left once the number drops `hysteresis` below it, and a
+/// change of level only shows once it has lasted for `min_duration` seconds.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct Threshold {
+    pub name: String,
+    pub instance: Option<String>,
+    pub levels: Vec<Level>,
+    pub hysteresis: Option<f64>,
+    pub min_duration: Option<u64>,
+}
+
+impl Threshold {
+    fn matches(&self, block: &Block) -> bool {
+        block.name.as_ref() == Some(&self.name)
+            && (self.instance.is_none() || self.instance == block.instance)
+    }
+
+    /// The level that `value` belongs to, given that the block is currently
+    /// at level `current`. Levels are sorted from the highest to the lowest.
+    fn level(&self, value: f64, current: Option<usize>) -> Option<usize> {
+        let level = self.levels.iter().position(|l| value > l.above);
+        let hysteresis = self.hysteresis.unwrap_or(0.0);
+
+        match (current, level) {
+            (Some(current), Some(level)) if level <= current => Some(level),
+            (Some(current), _) if value > self.levels[current].above - hysteresis => Some(current),
+            _ => level,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    level: Option<usize>,
+    /// A level that the block is headed for, and since when.
+    pending: Option<(Option<usize>, Instant)>,
+}
+
+#[derive(Default)]
+pub struct Thresholds {
+    thresholds: Vec<Threshold>,
+    states: HashMap<(usize, Option<String>, Option<String>), State>,
+}
+
+impl Thresholds {
+    /// Adds `threshold`, leaving out the levels with a NaN `above`, as no
+    /// number is ever above it.
+    pub fn add(&mut self, threshold: Threshold) {
+        let mut threshold = threshold;
+        threshold.levels.retain(|level| !level.above.is_nan());
+        threshold.levels.sort_by(|a, b| b.above.partial_cmp(&a.above).unwrap_or(Ordering::Equal));
+        self.thresholds.push(threshold);
+    }
+
+    pub fn clear(&mut self) {
+        self.thresholds.clear();
+        self.states.clear();
+    }
+
+    pub fn apply(&mut self, blocks: &mut [Block], now: Instant) {
+        for (i, threshold) in self.thresholds.iter().enumerate() {
+            for block in blocks.iter_mut().filter(|b| threshold.matches(b)) {
+                let value = match first_number(&block.full_text) {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                let key = (i, block.name.clone(), block.instance.clone());
+                let state = self.states.entry(key).or_insert_with(State::default);
+                let level = threshold.level(value, state.level);
+                let min_duration = Duration::from_secs(threshold.min_duration.unwrap_or(0));
+
+                if level == state.level {
+                    state.pending = None;
+                } else {
+                    let since = match state.pending {
+                        Some((pending, since)) if pending == level => since,
+                        _ => now,
+                    };
+                    if now.duration_since(since) >= min_duration {
+                        state.level = level;
+                        state.pending = None;
+                    } else {
+                        state.pending = Some((level, since));
+                    }
+                }
+
+                if let Some(level) = state.level.map(|l| &threshold.levels[l]) {
+                    if level.color.is_some() {
+                        block.color = level.color.clone();
+                    }
+                    if level.urgent.is_some() {
+                        block.urgent = level.urgent;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The first number in `text`, like `12.5` in `CPU: 12.5%`.
+fn first_number(text: &str) -> Option<f64> {
+    let start = match text.find(|c: char| c.is_ascii_digit()) {
+        Some(start) => start,
+        None => return None,
+    };
+    let start = if text[..start].ends_with('-') { start - 1 } else { start };
+
+    let mut seen_dot = false;
+    let end = text[start + 1..]
+        .find(|c: char| {
+            if c == '.' && !seen_dot {
+                seen_dot = true;
+                false
+            } else {
+                !c.is_ascii_digit()
+            }
+        })
+        .map_or(text.len(), |end| start + 1 + end);
+
+    text[start..end].trim_end_matches('.').parse().ok()
+}
+
+#[test]
+fn test_first_number() {
+    assert_eq!(Some(12.5), first_number("CPU: 12.5%"));
+    assert_eq!(Some(42.0), first_number("42. GiB"));
+    assert_eq!(Some(-3.0), first_number("T: -3°C"));
+    assert_eq!(Some(0.5), first_number("0.50 0.40 0.30"));
+    assert_eq!(None, first_number("W: down"));
+}
+
+#[test]
+fn test_thresholds() {
+    use rustc_serialize::json;
+
+    let mut thresholds = Thresholds::default();
+    thresholds.add(json::decode(r##"{
+        "name": "cpu_usage", "hysteresis": 5, "min_duration": 10,
+        "levels": [{ "above": 70, "color": "#FFFF00" },
+                   { "above": 90, "color": "#FF0000", "urgent": true }]
+    }"##).unwrap());
+
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut check = |secs, text: &str| {
+        let mut blocks = vec![Block {
+            full_text: text.to_string(),
+            name: Some("cpu_usage".to_string()),
+            .. Default::default()
+        }];
+        thresholds.apply(&mut blocks, at(secs));
+        (blocks[0].color.clone(), blocks[0].urgent)
+    };
+    let yellow = (Some("#FFFF00".to_string()), None);
+    let red = (Some("#FF0000".to_string()), Some(true));
+
+    assert_eq!((None, None), check(0, "CPU 95%"));
+    assert_eq!((None, None), check(5, "CPU 95%"));
+    assert_eq!(red, check(10, "CPU 95%"));
+    // Within the hysteresis of the red level
+    assert_eq!(red, check(20, "CPU 87%"));
+    assert_eq!(red, check(30, "CPU 80%"));
+    assert_eq!(yellow, check(40, "CPU 80%"));
+    // A short spike does not last for long enough to show
+    assert_eq!(yellow, check(50, "CPU 99%"));
+    assert_eq!(yellow, check(55, "CPU 75%"));
+    assert_eq!(yellow, check(60, "CPU 67%"));
+    assert_eq!(yellow, check(65, "CPU 10%"));
+    assert_eq!((None, None), check(75, "CPU 10%"));
+}
+
+#[test]
+fn test_nan_level() {
+    let level = |above| Level { above: above, color: Some(above.to_string()), urgent: None };
+    let mut thresholds = Thresholds::default();
+    thresholds.add(Threshold {
+        name: "load".to_string(),
+        instance: None,
+        levels: vec![level(1.0), level(::std::f64::NAN), level(2.0)],
+        hysteresis: None,
+        min_duration: None,
+    });
+    assert_eq!(vec![2.0, 1.0], thresholds.thresholds[0].levels.iter().map(|l| l.above).collect::<Vec<_>>());
+}
@@ -0,0 +1,397 @@
+use libc::{self, c_int};
+
+use std::cmp;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Error, ErrorKind};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use event::Event;
+use utf8::{self, Utf8Policy};
+use {parse_header, parse_status_line, signal, Block, Header, R3Error, R3Result, PROTOCOL_VERSION};
+
+/// How long to wait before restarting an upstream the first time it dies.
+const MIN_RESTART_DELAY_SECS: u64 = 1;
+/// The delay is doubled on every restart, up to this limit.
+const MAX_RESTART_DELAY_SECS: u64 = 64;
+/// If an upstream has been running for this long, it is not considered to be
+/// stuck in a crash loop, and the delay is reset.
+const RESTART_DELAY_RESET_SECS: u64 = 300;
+
+/// A program that r3status wraps, which writes the header and the status
+/// lines of the i3bar protocol on its stdout. Defaults to i3status, but can be
+/// anything that speaks the protocol, like i3blocks or a script.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct Upstream {
+    pub command: String,
+    pub args: Option<Vec<String>>,
+    /// The signal that makes the upstream print a fresh status line, which is
+    /// sent when the bar is shown again. Defaults to `SIGUSR1` for i3status,
+    /// and to none for anything else, as the default action of most signals
+    /// is to terminate the process.
+    pub refresh_signal: Option<usize>,
+    /// Put in front of the `name` of every block from the upstream, to keep
+    /// them apart from the blocks of other upstreams.
+    pub name_prefix: Option<String>,
+}
+
+impl Default for Upstream {
+    fn default() -> Upstream {
+        Upstream::new("i3status", Vec::new())
+    }
+}
+
+impl Upstream {
+    pub fn new(command: &str, args: Vec<String>) -> Upstream {
+        Upstream {
+            command: command.to_string(),
+            args: Some(args),
+            refresh_signal: None,
+            name_prefix: None,
+        }
+    }
+
+    /// The name of the command, to refer to the upstream by in messages.
+    pub fn name(&self) -> &str {
+        Path::new(&self.command).file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.command)
+    }
+
+    pub fn is_i3status(&self) -> bool {
+        self.name() == "i3status"
+    }
+
+    pub fn refresh_signal(&self) -> Option<c_int> {
+        match self.refresh_signal {
+            Some(sig) => Some(sig as c_int),
+            None if self.is_i3status() => Some(libc::SIGUSR1),
+            None => None,
+        }
+    }
+
+    /// Spawns the upstream with its stdout piped. `config` is passed on to
+    /// i3status with `-c`, other upstreams get their config in their `args`.
+    pub fn spawn<P: AsRef<Path>>(&self, config: Option<P>) -> io::Result<Child> {
+        let mut cmd = Command::new(&self.command);
+
+        if let Some(config) = config {
+            if self.is_i3status() {
+                let config = config.as_ref();
+                try!(check_config_file(config));
+                cmd.arg("-c").arg(config);
+            }
+        }
+        if let Some(ref args) = self.args {
+            cmd.args(args);
+        }
+
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+    }
+}
+
+/// What the upstreams have in common with the rest of r3status.
+#[derive(Clone)]
+pub struct Shared {
+    pub config_file: Option<String>,
+    pub restart: bool,
+    pub utf8_policy: Utf8Policy,
+    /// The number of invalid UTF-8 sequences from all of the upstreams.
+    pub invalid_utf8: Arc<AtomicUsize>,
+    pub paused: Arc<AtomicBool>,
+    pub events: Sender<Event>,
+}
+
+/// Runs `upstream` on a thread of its own, which keeps reporting its status
+/// lines until r3status stops listening. `pid` is kept up to date with the
+/// pid of the upstream, or 0 when it is not running.
+pub fn spawn(index: usize, upstream: Upstream, pid: Arc<AtomicUsize>, shared: Shared) {
+    let runner = Runner {
+        index: index,
+        upstream: upstream,
+        shared: shared,
+        pid: pid,
+        child: None,
+        child_started: Instant::now(),
+        restart_delay: Duration::from_secs(MIN_RESTART_DELAY_SECS),
+        header_sent: false,
+        reader: None,
+        raw_line: Vec::new(),
+        buffer: String::new(),
+        line_invalid_utf8: Vec::new(),
+    };
+    thread::spawn(move || runner.run());
+}
+
+struct Runner {
+    index: usize,
+    upstream: Upstream,
+    shared: Shared,
+    pid: Arc<AtomicUsize>,
+    child: Option<Child>,
+    child_started: Instant,
+    restart_delay: Duration,
+    header_sent: bool,
+    reader: Option<BufReader<ChildStdout>>,
+    raw_line: Vec<u8>,
+    buffer: String,
+    /// The offsets in `buffer` where invalid UTF-8 was replaced.
+    line_invalid_utf8: Vec<usize>,
+}
+
+impl Runner {
+    /// Keeps the upstream running, restarting it with an exponentially
+    /// increasing delay after it has died or stopped making sense.
+    fn run(mut self) {
+        loop {
+            let cause = match self.start() {
+                Ok(()) => self.pipe_lines(),
+                Err(e) => e,
+            };
+            let status = self.stop_child();
+            let cause = match cause {
+                R3Error::Eof(_) => R3Error::Eof(status),
+                e => e,
+            };
+
+            if !self.shared.restart {
+                let _ = self.send(Event::Exit(cause));
+                return;
+            }
+
+            if self.child_started.elapsed() > Duration::from_secs(RESTART_DELAY_RESET_SECS) {
+                self.restart_delay = Duration::from_secs(MIN_RESTART_DELAY_SECS);
+            }
+            let msg = format!("{}: {}, restarting in {}s",
+                              self.upstream.name(), cause, self.restart_delay.as_secs());
+            if self.send(Event::Message(self.index, msg)).is_err() {
+                return;
+            }
+
+            thread::sleep(self.restart_delay);
+            self.restart_delay = cmp::min(self.restart_delay * 2,
+                                          Duration::from_secs(MAX_RESTART_DELAY_SECS));
+        }
+    }
+
+    /// Spawns the upstream and reads its header and the start of the array.
+    /// The header is only reported the first time, when restarted the
+    /// upstream has to look like one continuous stream. A garbled header is
+    /// reported as an error, as the status lines might still be fine.
+    fn start(&mut self) -> R3Result<()> {
+        try!(self.spawn_child());
+
+        match self.read_header() {
+            Ok(header) => if !self.header_sent {
+                self.header_sent = true;
+                try!(self.send(Event::Header(self.index, header)));
+            },
+            Err(e @ R3Error::Json { .. }) => try!(self.send(Event::Error(self.index, e))),
+            Err(e) => return Err(e),
+        }
+        self.read_array_start()
+    }
+
+    /// Reports status lines until the upstream fails, and returns why.
+    fn pipe_lines(&mut self) -> R3Error {
+        loop {
+            let event = match self.read_status_line() {
+                Ok(blocks) => Event::Line(self.index, blocks),
+                // A single garbled line is not worth restarting the upstream for
+                Err(e @ R3Error::Json { .. }) => Event::Error(self.index, e),
+                Err(e) => return e,
+            };
+            if let Err(e) = self.send(event) {
+                return e;
+            }
+        }
+    }
+
+    fn send(&self, event: Event) -> R3Result<()> {
+        self.shared.events.send(event).map_err(|_| {
+            R3Error::Child("r3status has stopped listening".to_string())
+        })
+    }
+
+    fn spawn_child(&mut self) -> R3Result<()> {
+        let mut child = try!(self.upstream.spawn(self.shared.config_file.as_ref()).map_err(|e| {
+            R3Error::Child(format!("failed to spawn {}: {}", self.upstream.name(), e))
+        }));
+
+        match child.stdout.take() {
+            Some(out) => self.reader = Some(BufReader::new(out)),
+            None => {
+                let _ = child.kill();
+                return Err(R3Error::Child(format!("Failed to aquire handle to the `stdout` of {}",
+                                                  self.upstream.name())));
+            }
+        }
+
+        self.pid.store(child.id() as usize, Ordering::SeqCst);
+        if self.shared.paused.load(Ordering::SeqCst) {
+            signal::kill(child.id() as usize, libc::SIGSTOP);
+        }
+        self.child = Some(child);
+        self.child_started = Instant::now();
+        Ok(())
+    }
+
+    /// Kills the upstream, in case it is still running, and collects its
+    /// exit status.
+    fn stop_child(&mut self) -> Option<ExitStatus> {
+        self.pid.store(0, Ordering::SeqCst);
+        self.reader = None;
+
+        self.child.take().and_then(|mut child| {
+            let _ = child.kill();
+            child.wait().ok()
+        })
+    }
+
+    /// Reads the next line from the upstream into the buffer, treating the
+    /// end of its output as an error.
+    fn next_line(&mut self) -> R3Result<()> {
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return Err(R3Error::Child("A reader has not been set for the process"
+                                              .to_string())),
+        };
+
+        self.raw_line.clear();
+        self.buffer.clear();
+        if try!(reader.read_until('\n' as u8, &mut self.raw_line)) == 0 {
+            return Err(R3Error::Eof(None));
+        }
+
+        self.line_invalid_utf8 = utf8::decode(&self.raw_line, self.shared.utf8_policy,
+                                              &mut self.buffer);
+        self.shared.invalid_utf8.fetch_add(self.line_invalid_utf8.len(), Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Reads the header. Newer versions of the protocol are assumed to be
+    /// backwards compatible, and are only warned about.
+    fn read_header(&mut self) -> R3Result<Header> {
+        try!(self.next_line());
+
+        let header = try!(parse_header(&self.buffer).map_err(|e| R3Error::json(&self.buffer, e)));
+        if header.version < PROTOCOL_VERSION {
+            return Err(R3Error::Protocol(
+                format!("unsupported protocol version {}", header.version)));
+        } else if header.version > PROTOCOL_VERSION {
+            eprintln!("r3status: {} speaks protocol version {}, treating it as version {}",
+                      self.upstream.name(), header.version, PROTOCOL_VERSION);
+        }
+        Ok(header)
+    }
+
+    fn read_array_start(&mut self) -> R3Result<()> {
+        try!(self.next_line());
+
+        let line = self.buffer.trim();
+        if line != "[" {
+            return Err(R3Error::Protocol(
+                format!("expected the start of the status array, got `{}`", line)));
+        }
+        Ok(())
+    }
+
+    fn read_status_line(&mut self) -> R3Result<Vec<Block>> {
+        try!(self.next_line());
+
+        let mut blocks = try!(parse_status_line(&self.buffer).map_err(|e| {
+            R3Error::json(&self.buffer, e)
+        }));
+        if self.shared.utf8_policy == Utf8Policy::DropBlock && !self.line_invalid_utf8.is_empty() {
+            let invalid = utf8::invalid_blocks(&self.buffer, &self.line_invalid_utf8);
+            let mut invalid = invalid.into_iter();
+            blocks.retain(|_| !invalid.next().unwrap_or(false));
+        }
+        if let Some(ref prefix) = self.upstream.name_prefix {
+            for name in blocks.iter_mut().filter_map(|b| b.name.as_mut()) {
+                name.insert_str(0, prefix);
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+/// Makes sure that the config file exists and is readable before it is handed
+/// to i3status, which would otherwise silently fall back to its default config.
+pub fn check_config_file(config: &Path) -> io::Result<()> {
+    let meta = try!(fs::metadata(config).map_err(|e| {
+        Error::new(e.kind(), format!("i3status config `{}` does not exist: {}", config.display(), e))
+    }));
+
+    if !meta.is_file() {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("i3status config `{}` is not a file", config.display())));
+    }
+
+    File::open(config).map(|_| ()).map_err(|e| {
+        Error::new(e.kind(), format!("i3status config `{}` is not readable: {}", config.display(), e))
+    })
+}
+
+#[test]
+fn test_check_config_file() {
+    assert!(check_config_file(Path::new("Cargo.toml")).is_ok());
+    assert_eq!(ErrorKind::NotFound,
+               check_config_file(Path::new("does/not/exist")).unwrap_err().kind());
+    assert_eq!(ErrorKind::InvalidInput,
+               check_config_file(Path::new("src")).unwrap_err().kind());
+}
+
+#[test]
+fn test_upstream() {
+    let i3status = Upstream::default();
+    assert!(i3status.is_i3status());
+    assert_eq!(Some(libc::SIGUSR1), i3status.refresh_signal());
+
+    let i3status = Upstream::new("/usr/local/bin/i3status", Vec::new());
+    assert!(i3status.is_i3status());
+
+    let i3blocks = Upstream::new("i3blocks", Vec::new());
+    assert_eq!(None, i3blocks.refresh_signal());
+}
+
+#[test]
+fn test_run_upstream() {
+    use std::sync::mpsc;
+
+    let script = r#"echo '{"version":1}'; echo '['; echo '[{"full_text":"up","name":"ci"}]'; exit 3"#;
+    let mut upstream = Upstream::new("sh", vec!["-c".to_string(), script.to_string()]);
+    upstream.name_prefix = Some("build_".to_string());
+
+    let (tx, rx) = mpsc::channel();
+    let shared = Shared {
+        config_file: Some("i3status.conf".to_string()),
+        restart: false,
+        utf8_policy: Utf8Policy::default(),
+        invalid_utf8: Arc::new(AtomicUsize::new(0)),
+        paused: Arc::new(AtomicBool::new(false)),
+        events: tx,
+    };
+    spawn(2, upstream, Arc::new(AtomicUsize::new(0)), shared);
+
+    match rx.recv().unwrap() {
+        Event::Header(2, header) => assert_eq!(1, header.version),
+        other => panic!("expected a header, got {:?}", other),
+    }
+    match rx.recv().unwrap() {
+        Event::Line(2, blocks) => assert_eq!(Some("build_ci".to_string()), blocks[0].name),
+        other => panic!("expected a line, got {:?}", other),
+    }
+    match rx.recv().unwrap() {
+        Event::Exit(R3Error::Eof(Some(status))) => assert_eq!(Some(3), status.code()),
+        other => panic!("expected an exit, got {:?}", other),
+    }
+}
@@ -0,0 +1,142 @@
+use rustc_serialize::{Decodable, Decoder};
+
+use std::str;
+
+/// What to do with bytes from i3status that are not valid UTF-8, like an SSID
+/// or a mount label in latin-1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Utf8Policy {
+    /// Replace every invalid sequence with U+FFFD.
+    Lossy,
+    /// Replace every invalid byte with its value, like `\xE6`.
+    HexEscape,
+    /// Leave out the blocks that contain invalid sequences.
+    DropBlock,
+}
+
+impl Default for Utf8Policy {
+    fn default() -> Utf8Policy {
+        Utf8Policy::Lossy
+    }
+}
+
+impl Decodable for Utf8Policy {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Utf8Policy, D::Error> {
+        match &try!(d.read_str())[..] {
+            "lossy" => Ok(Utf8Policy::Lossy),
+            "hex"   => Ok(Utf8Policy::HexEscape),
+            "drop"  => Ok(Utf8Policy::DropBlock),
+            other   => Err(d.error(&format!("`{}` is not a valid utf8 policy", other))),
+        }
+    }
+}
+
+/// Appends `bytes` to `out`, handling invalid sequences according to
+/// `policy`, and returns the offsets in `out` where the invalid sequences
+/// were replaced.
+///
+/// Invalid sequences are only expected inside of JSON strings, so the hex
+/// escapes are written with an escaped backslash, to decode as `\xE6`.
+/// Blocks that are to be dropped are found through the offsets, and get
+/// U+FFFD in the meantime, as with `Lossy`.
+pub fn decode(bytes: &[u8], policy: Utf8Policy, out: &mut String) -> Vec<usize> {
+    let mut bytes = bytes;
+    let mut invalid = Vec::new();
+
+    loop {
+        match str::from_utf8(bytes) {
+            Ok(s) => {
+                out.push_str(s);
+                return invalid;
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                // Everything up to `valid_up_to` has just been validated
+                out.push_str(str::from_utf8(valid).unwrap());
+
+                // A sequence that is cut short by the end of the input has no length
+                let len = e.error_len().unwrap_or(rest.len());
+                invalid.push(out.len());
+                match policy {
+                    Utf8Policy::Lossy | Utf8Policy::DropBlock => out.push('\u{FFFD}'),
+                    Utf8Policy::HexEscape => for b in &rest[..len] {
+                        out.push_str(&format!("\\\\x{:02X}", b));
+                    },
+                }
+                bytes = &rest[len..];
+            }
+        }
+    }
+}
+
+/// Which of the blocks on the status line `line`, which has to be valid
+/// JSON, contain one of the offsets in `invalid`. The JSON is scanned for the
+/// bounds of the blocks, as the decoded blocks do not know where they came from.
+pub fn invalid_blocks(line: &str, invalid: &[usize]) -> Vec<bool> {
+    let mut blocks = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, b) in line.bytes().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match b {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                if depth == 2 && b == b'{' {
+                    start = i;
+                }
+            }
+            b']' | b'}' => {
+                if depth == 2 && b == b'}' {
+                    blocks.push(invalid.iter().any(|&offset| start < offset && offset < i));
+                }
+                depth -= 1;
+            }
+            _ => (),
+        }
+    }
+    blocks
+}
+
+#[test]
+fn test_decode() {
+    let line = b"{\"full_text\":\"W: Caf\xE9 \xF0\x9F\x93\xB6\"}\n";
+    let mut out = String::new();
+
+    assert_eq!(vec![20], decode(line, Utf8Policy::Lossy, &mut out));
+    assert_eq!("{\"full_text\":\"W: Caf\u{FFFD} \u{1F4F6}\"}\n", out);
+
+    out.clear();
+    assert_eq!(vec![20], decode(line, Utf8Policy::HexEscape, &mut out));
+    assert_eq!("{\"full_text\":\"W: Caf\\\\xE9 \u{1F4F6}\"}\n", out);
+
+    out.clear();
+    assert!(decode(b"[\n", Utf8Policy::DropBlock, &mut out).is_empty());
+    assert_eq!("[\n", out);
+
+    out.clear();
+    assert_eq!(vec![0, 5, 12], decode(b"\xFF\xFEok\xE2\x82", Utf8Policy::HexEscape, &mut out));
+    assert_eq!("\\\\xFF\\\\xFEok\\\\xE2\\\\x82", out);
+}
+
+#[test]
+fn test_invalid_blocks() {
+    let line = b",[{\"full_text\":\"\xEF\xBF\xBD\"},{\"full_text\":\"{\\\"}\",\"color\":\"#\xE6\"},{\"full_text\":\"ok\"}]";
+    let mut out = String::new();
+    let invalid = decode(line, Utf8Policy::DropBlock, &mut out);
+    assert_eq!(1, invalid.len());
+    assert_eq!(vec![false, true, false], invalid_blocks(&out, &invalid));
+}